@@ -0,0 +1,347 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use crate::state::GameState;
+
+/// How the unclaimed boxes of a component are joined together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentKind {
+    /// Boxes in a row, each joined to the next by an undrawn edge, with both
+    /// ends open to the border or to a box with three or more undrawn sides.
+    Chain,
+    /// Boxes joined in a ring with no way out.
+    Loop,
+    /// A single box with no undrawn edge shared with a neighbouring chain box.
+    Isolated,
+}
+
+/// A chain, loop or isolated box. Every box in it has one or two undrawn
+/// sides, so whoever draws inside it hands the whole component over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Component {
+    pub kind: ComponentKind,
+    /// The boxes in order along the chain or around the loop, as `(y, x)`.
+    pub boxes: Vec<(usize, usize)>,
+    /// Whether a box in it can be taken right now.
+    pub capturable: bool,
+}
+
+impl Component {
+    pub fn len(&self) -> usize {
+        self.boxes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boxes.is_empty()
+    }
+
+    /// Chains of three or more boxes count towards the long chain rule.
+    pub fn is_long_chain(&self) -> bool {
+        self.kind == ComponentKind::Chain && self.len() >= 3
+    }
+}
+
+/// The structure of a position, as used by the long chain rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    /// Chains, loops and isolated boxes, largest first.
+    pub components: Vec<Component>,
+    /// Unclaimed boxes with three or four undrawn sides. Chains end at these,
+    /// and the shape of the endgame is not settled while any remain.
+    pub open_boxes: Vec<(usize, usize)>,
+    /// Moves that draw no third side on any box.
+    pub safe_moves: usize,
+    /// In a two player game, the player the long chain rule gives control to:
+    /// the one who will not have to open the first long chain or loop.
+    pub control: Option<u8>,
+    /// The boxes the player in control gives away to keep control to the end,
+    /// two at the end of each long chain and four of each loop except the last.
+    pub sacrifices: u32,
+    /// The margin the player in control wins the long chains and loops by after
+    /// paying for control.
+    pub controlled_value: i32,
+}
+
+impl Analysis {
+    pub fn long_chains(&self) -> usize {
+        self.components.iter().filter(|c| c.is_long_chain()).count()
+    }
+
+    pub fn loops(&self) -> usize {
+        self.components
+            .iter()
+            .filter(|c| c.kind == ComponentKind::Loop)
+            .count()
+    }
+
+    /// Chains of one or two boxes, including isolated boxes.
+    pub fn short_chains(&self) -> usize {
+        self.components
+            .iter()
+            .filter(|c| c.kind != ComponentKind::Loop && !c.is_long_chain())
+            .count()
+    }
+}
+
+/// Breaks the unclaimed boxes of a position into chains, loops and isolated
+/// boxes and works out who the long chain rule favours.
+pub fn analyze(state: &GameState) -> Analysis {
+    let mut chain_boxes = HashSet::new();
+    let mut open_boxes = Vec::new();
+    for y in 0..state.height() - 1 {
+        for x in 0..state.width() - 1 {
+            match state.sides(y, x) {
+                4 => (),
+                2 | 3 => {
+                    chain_boxes.insert((y, x));
+                }
+                _ => open_boxes.push((y, x)),
+            }
+        }
+    }
+
+    let mut components = Vec::new();
+    let mut seen = HashSet::new();
+    let mut starts: Vec<(usize, usize)> = chain_boxes.iter().copied().collect();
+    starts.sort();
+    // Walk from the ends of chains first so each chain is listed in order
+    starts.sort_by_key(|b| chain_neighbours(state, &chain_boxes, *b).len());
+    for start in starts {
+        if seen.contains(&start) {
+            continue;
+        }
+        let mut boxes = vec![start];
+        seen.insert(start);
+        let mut current = start;
+        while let Some(next) = chain_neighbours(state, &chain_boxes, current)
+            .into_iter()
+            .find(|b| !seen.contains(b))
+        {
+            seen.insert(next);
+            boxes.push(next);
+            current = next;
+        }
+
+        // A ring closes back on its start and has no way out
+        let closed = boxes.len() > 2
+            && boxes.iter().all(|b| {
+                state.sides(b.0, b.1) == 2 && chain_neighbours(state, &chain_boxes, *b).len() == 2
+            });
+        let kind = if closed {
+            ComponentKind::Loop
+        } else if boxes.len() == 1 {
+            ComponentKind::Isolated
+        } else {
+            ComponentKind::Chain
+        };
+        let capturable = boxes.iter().any(|b| state.sides(b.0, b.1) == 3);
+        components.push(Component {
+            kind,
+            boxes,
+            capturable,
+        });
+    }
+    components.sort_by_key(|c| Reverse(c.len()));
+
+    let safe_moves = state
+        .legal_moves()
+        .into_iter()
+        .filter(|m| !state.gives_third_side(*m))
+        .count();
+
+    let mut analysis = Analysis {
+        components,
+        open_boxes,
+        safe_moves,
+        control: None,
+        sacrifices: 0,
+        controlled_value: 0,
+    };
+    analysis.control = control(state, analysis.long_chains());
+    let (sacrifices, controlled_value) = controlled_value(&analysis);
+    analysis.sacrifices = sacrifices;
+    analysis.controlled_value = controlled_value;
+
+    analysis
+}
+
+/// The chain boxes joined to `(y, x)` by an undrawn edge.
+fn chain_neighbours(
+    state: &GameState,
+    chain_boxes: &HashSet<(usize, usize)>,
+    (y, x): (usize, usize),
+) -> Vec<(usize, usize)> {
+    state
+        .open_edges(y, x)
+        .into_iter()
+        .flat_map(|edge| state.adjacent_boxes(edge))
+        .filter(|b| *b != (y, x) && chain_boxes.contains(b))
+        .collect()
+}
+
+/// Every turn but the last ends with a move that completes no box, so the
+/// number of turns left is fixed by the edges and boxes left plus the moves
+/// that will complete two boxes at once. Each long chain but the last adds
+/// one of those when its owner declines it, so with `long_chains` chains the
+/// player to move takes the last turn when the sum below is odd. A chain
+/// that is already being taken may end in a move that completes two boxes,
+/// which this does not foresee.
+fn control(state: &GameState, long_chains: usize) -> Option<u8> {
+    if state.player_count() != 2 || state.is_over() {
+        return None;
+    }
+    let remaining_edges = state.legal_moves().len() as i64;
+    let remaining_boxes = (0..state.height() - 1)
+        .flat_map(|y| (0..state.width() - 1).map(move |x| (y, x)))
+        .filter(|(y, x)| state.owner(*y, *x) == 0)
+        .count() as i64;
+
+    let player = state.current_player();
+    if (remaining_edges + long_chains as i64 - remaining_boxes).rem_euclid(2) == 1 {
+        Some(player)
+    } else {
+        Some(player % 2 + 1)
+    }
+}
+
+/// The sacrifices needed to keep control and the margin left afterwards. The
+/// last long component is taken whole, so it costs nothing.
+fn controlled_value(analysis: &Analysis) -> (u32, i32) {
+    let long_chains = analysis.long_chains() as u32;
+    let loops = analysis.loops() as u32;
+    let boxes: usize = analysis
+        .components
+        .iter()
+        .filter(|c| c.kind == ComponentKind::Loop || c.is_long_chain())
+        .map(Component::len)
+        .sum();
+
+    let refund = if long_chains > 0 {
+        2
+    } else if loops > 0 {
+        4
+    } else {
+        0
+    };
+    let sacrifices = 2 * long_chains + 4 * loops - refund;
+
+    (sacrifices, boxes as i32 - 2 * sacrifices as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Direction, Move};
+
+    /// A board of `height` by `width` dots with the given edges drawn in turn.
+    fn position(height: u16, width: u16, edges: &[(usize, usize, Direction)]) -> GameState {
+        let mut state = GameState::new(height, width, 2);
+        for (y, x, direction) in edges {
+            state.apply_move(Move::new(*y, *x, *direction)).unwrap();
+        }
+
+        state
+    }
+
+    /// Every horizontal edge of a board one box high, leaving a single row
+    /// of boxes joined end to end.
+    fn corridor(width: usize) -> Vec<(usize, usize, Direction)> {
+        (0..2)
+            .flat_map(|y| (0..width - 1).map(move |x| (y, x, Direction::Horizontal)))
+            .collect()
+    }
+
+    #[test]
+    fn finds_a_long_chain_in_order() {
+        let state = position(2, 5, &corridor(5));
+        let analysis = analyze(&state);
+
+        assert_eq!(
+            analysis.components,
+            vec![Component {
+                kind: ComponentKind::Chain,
+                boxes: vec![(0, 0), (0, 1), (0, 2), (0, 3)],
+                capturable: false,
+            }]
+        );
+        assert_eq!(
+            (
+                analysis.long_chains(),
+                analysis.loops(),
+                analysis.short_chains()
+            ),
+            (1, 0, 0)
+        );
+        assert!(analysis.open_boxes.is_empty());
+        assert_eq!(analysis.safe_moves, 0);
+        // Player 1 has to open the only chain, so player 2 takes all of it
+        assert_eq!(analysis.control, Some(2));
+        assert_eq!((analysis.sacrifices, analysis.controlled_value), (0, 4));
+    }
+
+    #[test]
+    fn finds_a_loop() {
+        use Direction::{Horizontal, Vertical};
+        let border = [
+            (0, 0, Horizontal),
+            (0, 1, Horizontal),
+            (2, 0, Horizontal),
+            (2, 1, Horizontal),
+            (0, 0, Vertical),
+            (1, 0, Vertical),
+            (0, 2, Vertical),
+            (1, 2, Vertical),
+        ];
+        let analysis = analyze(&position(3, 3, &border));
+
+        assert_eq!(analysis.components.len(), 1);
+        assert_eq!(analysis.components[0].kind, ComponentKind::Loop);
+        assert_eq!(analysis.components[0].len(), 4);
+        assert_eq!((analysis.long_chains(), analysis.loops()), (0, 1));
+        assert_eq!(analysis.control, Some(2));
+        assert_eq!((analysis.sacrifices, analysis.controlled_value), (0, 4));
+    }
+
+    #[test]
+    fn splits_a_chain_at_a_drawn_edge() {
+        let mut edges = corridor(5);
+        edges.push((0, 2, Direction::Vertical));
+        let analysis = analyze(&position(2, 5, &edges));
+
+        // Both halves end in a box with three sides, ready to be taken
+        assert_eq!(analysis.components.len(), 2);
+        assert!(analysis
+            .components
+            .iter()
+            .all(|c| c.kind == ComponentKind::Chain && c.len() == 2 && c.capturable));
+        assert_eq!((analysis.long_chains(), analysis.short_chains()), (0, 2));
+        assert_eq!((analysis.sacrifices, analysis.controlled_value), (0, 0));
+    }
+
+    #[test]
+    fn counts_parity_for_each_long_chain() {
+        // Two chains of three, side by side
+        let mut edges = corridor(4);
+        edges.extend((0..3).map(|x| (2, x, Direction::Horizontal)));
+        let state = position(3, 4, &edges);
+        let analysis = analyze(&state);
+
+        assert_eq!(analysis.long_chains(), 2);
+        // Keeping control gives away two boxes of the first chain
+        assert_eq!((analysis.sacrifices, analysis.controlled_value), (2, 2));
+        // Eight edges, two long chains and six boxes are left with player 2
+        // to move. The sum is even, so control goes to player 1.
+        assert_eq!(state.current_player(), 2);
+        assert_eq!(analysis.control, Some(1));
+    }
+
+    #[test]
+    fn leaves_open_boxes_out_of_components() {
+        let analysis = analyze(&GameState::new(3, 3, 2));
+
+        assert!(analysis.components.is_empty());
+        assert_eq!(analysis.open_boxes.len(), 4);
+        assert_eq!(analysis.safe_moves, 12);
+        assert_eq!(analyze(&GameState::new(3, 3, 3)).control, None);
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::mcts::{Budget, MctsBot};
+use crate::rng::Rng;
+use crate::state::{GameState, Move};
+
+/// A computer player. Bots are sent to another thread to think in server
+/// games.
+pub trait Bot: Send {
+    /// Picks a legal move for the current player. Only called while the game
+    /// is not over.
+    fn choose_move(&mut self, state: &GameState) -> Move;
+}
+
+/// The built-in computer players, from weakest to strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotKind {
+    /// Draws any legal edge at random.
+    Random,
+    /// Takes every box it can and avoids drawing third sides.
+    Greedy,
+    /// Searches the move tree with alpha-beta pruning.
+    Hard,
+    /// Monte Carlo tree search, for boards too big to search exhaustively.
+    Mcts(Budget),
+}
+
+impl BotKind {
+    pub fn build(self, rng: Rng) -> Box<dyn Bot> {
+        match self {
+            BotKind::Random => Box::new(RandomBot { rng }),
+            BotKind::Greedy => Box::new(GreedyBot { rng }),
+            BotKind::Hard => Box::new(SearchBot::new(rng)),
+            BotKind::Mcts(budget) => Box::new(MctsBot::new(rng, budget)),
+        }
+    }
+}
+
+/// The MCTS bot takes an optional budget after a colon, such as `mcts:5000`
+/// for iterations or `mcts:500ms` for time.
+impl FromStr for BotKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("mcts", budget)) => return Ok(BotKind::Mcts(budget.parse()?)),
+            Some(_) => return Err(()),
+            None => (),
+        }
+        match s {
+            "random" | "easy" => Ok(BotKind::Random),
+            "greedy" | "medium" => Ok(BotKind::Greedy),
+            "hard" | "search" => Ok(BotKind::Hard),
+            "mcts" => Ok(BotKind::Mcts(Budget::default())),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for BotKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BotKind::Random => write!(f, "random"),
+            BotKind::Greedy => write!(f, "greedy"),
+            BotKind::Hard => write!(f, "hard"),
+            BotKind::Mcts(budget) => write!(f, "mcts:{budget}"),
+        }
+    }
+}
+
+pub struct RandomBot {
+    rng: Rng,
+}
+
+impl Bot for RandomBot {
+    fn choose_move(&mut self, state: &GameState) -> Move {
+        let moves = state.legal_moves();
+        moves[self.rng.below(moves.len())]
+    }
+}
+
+pub struct GreedyBot {
+    rng: Rng,
+}
+
+impl Bot for GreedyBot {
+    fn choose_move(&mut self, state: &GameState) -> Move {
+        greedy_move(state, &mut self.rng)
+    }
+}
+
+/// Takes a box if one can be completed, otherwise plays an edge that draws no
+/// third side, otherwise gives away the fewest boxes.
+pub fn greedy_move(state: &GameState, rng: &mut Rng) -> Move {
+    let moves = state.legal_moves();
+
+    // Take any box that can be completed
+    let captures: Vec<Move> = moves
+        .iter()
+        .copied()
+        .filter(|m| state.completes(*m) > 0)
+        .collect();
+    if let Some(capture) = rng.choose(&captures) {
+        return *capture;
+    }
+
+    // Otherwise avoid handing a box to the next player
+    let safe: Vec<Move> = moves
+        .iter()
+        .copied()
+        .filter(|m| !state.gives_third_side(*m))
+        .collect();
+    if let Some(safe_move) = rng.choose(&safe) {
+        return *safe_move;
+    }
+
+    // Every move gives boxes away, so give away as few as possible
+    let fewest = moves
+        .iter()
+        .map(|m| sacrifice_size(state, *m))
+        .min()
+        .unwrap_or(0);
+    let smallest: Vec<Move> = moves
+        .into_iter()
+        .filter(|m| sacrifice_size(state, *m) == fewest)
+        .collect();
+    smallest[rng.below(smallest.len())]
+}
+
+/// The number of boxes the next player could take in a row after `edge` is
+/// drawn, following the chain of boxes it opens.
+pub fn sacrifice_size(state: &GameState, edge: Move) -> usize {
+    let mut drawn: HashSet<Move> = HashSet::from([edge]);
+    let mut taken: HashSet<(usize, usize)> = HashSet::new();
+    let mut pending = state.adjacent_boxes(edge);
+
+    while let Some((y, x)) = pending.pop() {
+        if taken.contains(&(y, x)) {
+            continue;
+        }
+        let open: Vec<Move> = state
+            .open_edges(y, x)
+            .into_iter()
+            .filter(|m| !drawn.contains(m))
+            .collect();
+        match open.len() {
+            // Completed as a side effect of the last edge taken
+            0 => {
+                taken.insert((y, x));
+            }
+            // Taken by drawing its last edge, which may open the next box
+            1 => {
+                taken.insert((y, x));
+                drawn.insert(open[0]);
+                pending.extend(state.adjacent_boxes(open[0]));
+            }
+            _ => (),
+        }
+    }
+
+    taken.len()
+}
+
+/// The moves that turn down the last two boxes of a chain instead of taking
+/// one of `captures`: the far edge of a box the capture would leave with
+/// three sides.
+pub fn double_deals(state: &GameState, captures: &[Move]) -> Vec<Move> {
+    let mut declines = Vec::new();
+    for capture in captures {
+        for (y, x) in state.adjacent_boxes(*capture) {
+            if state.sides(y, x) != 2 {
+                continue;
+            }
+            for edge in state.open_edges(y, x) {
+                if edge != *capture && !captures.contains(&edge) && !declines.contains(&edge) {
+                    declines.push(edge);
+                }
+            }
+        }
+    }
+
+    declines
+}
+
+/// Orders moves so the search sees captures first, then safe moves, then
+/// sacrifices from smallest to largest.
+pub fn ordered_moves(state: &GameState) -> Vec<Move> {
+    let mut scored: Vec<(usize, Move)> = state
+        .legal_moves()
+        .into_iter()
+        .map(|m| {
+            let rank = if state.completes(m) > 0 {
+                0
+            } else if !state.gives_third_side(m) {
+                1
+            } else {
+                2 + sacrifice_size(state, m)
+            };
+            (rank, m)
+        })
+        .collect();
+    scored.sort_by_key(|(rank, _)| *rank);
+
+    scored.into_iter().map(|(_, m)| m).collect()
+}
+
+/// The moves worth searching, captures first and then safe moves. A capture
+/// that cannot set up a box for anyone is always worth taking straight away,
+/// so when one exists it is the only candidate.
+fn search_moves(state: &GameState) -> Vec<Move> {
+    let moves = state.legal_moves();
+    let mut captures = Vec::new();
+    let mut safe = Vec::new();
+    let mut rest = Vec::new();
+    for m in moves {
+        if state.completes(m) > 0 {
+            let sets_up_box = state
+                .adjacent_boxes(m)
+                .into_iter()
+                .any(|(y, x)| state.sides(y, x) == 2);
+            if !sets_up_box {
+                return vec![m];
+            }
+            captures.push(m);
+        } else if !state.gives_third_side(m) {
+            safe.push(m);
+        } else {
+            rest.push(m);
+        }
+    }
+    captures.extend(safe);
+    captures.extend(rest);
+
+    captures
+}
+
+/// Plays greedily while safe moves remain on a big board, and otherwise runs
+/// an iterative deepening alpha-beta search within a node budget. Positions
+/// are scored for the bot against all other players combined.
+pub struct SearchBot {
+    rng: Rng,
+    max_nodes: usize,
+    nodes: usize,
+    root_player: u8,
+    /// Whether the depth limit stopped the last pass before the end of the game.
+    cut_off: bool,
+}
+
+/// Above this many legal moves the search only runs once no safe move is left,
+/// and above four times as many it never runs.
+const SEARCH_MOVE_LIMIT: usize = 30;
+
+impl SearchBot {
+    pub fn new(rng: Rng) -> SearchBot {
+        SearchBot {
+            rng,
+            max_nodes: 50_000,
+            nodes: 0,
+            root_player: 1,
+            cut_off: false,
+        }
+    }
+
+    fn evaluate(&self, state: &GameState) -> i32 {
+        let scores = state.scores();
+        let own = scores[(self.root_player - 1) as usize] as i32;
+        let total: i32 = scores.iter().map(|s| *s as i32).sum();
+
+        own - (total - own)
+    }
+
+    /// Returns `None` when the node budget runs out.
+    fn alpha_beta(
+        &mut self,
+        state: &mut GameState,
+        depth: usize,
+        mut alpha: i32,
+        mut beta: i32,
+    ) -> Option<i32> {
+        self.nodes += 1;
+        if self.nodes > self.max_nodes {
+            return None;
+        }
+        if state.is_over() {
+            return Some(self.evaluate(state));
+        }
+
+        // Past the depth limit keep following captures so chains are not cut in half
+        let mut moves = search_moves(state);
+        if depth == 0 {
+            let searched = moves.len();
+            moves.retain(|m| state.completes(*m) > 0);
+            self.cut_off |= moves.len() < searched;
+            if moves.is_empty() {
+                return Some(self.evaluate(state));
+            }
+        }
+
+        let maximising = state.current_player() == self.root_player;
+        let mut best = if maximising { i32::MIN } else { i32::MAX };
+        for player_move in moves {
+            if state.apply_move(player_move).is_err() {
+                continue;
+            }
+            let value = self.alpha_beta(state, depth.saturating_sub(1), alpha, beta);
+            state.undo();
+            let value = value?;
+
+            if maximising {
+                best = best.max(value);
+                alpha = alpha.max(value);
+            } else {
+                best = best.min(value);
+                beta = beta.min(value);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        Some(best)
+    }
+}
+
+impl Bot for SearchBot {
+    fn choose_move(&mut self, state: &GameState) -> Move {
+        let legal_moves = state.legal_moves();
+        let has_safe_move = legal_moves.iter().any(|m| !state.gives_third_side(*m));
+        let too_many = if has_safe_move {
+            SEARCH_MOVE_LIMIT
+        } else {
+            SEARCH_MOVE_LIMIT * 4
+        };
+        if legal_moves.len() > too_many {
+            return greedy_move(state, &mut self.rng);
+        }
+
+        self.root_player = state.current_player();
+        self.nodes = 0;
+        let mut scratch = state.clone();
+        let moves = ordered_moves(state);
+        let mut best_move = moves[0];
+
+        // Deepen until the budget runs out or the whole tree has been searched
+        for depth in 1..=moves.len() {
+            self.cut_off = false;
+            let mut best_value = i32::MIN;
+            let mut depth_best = best_move;
+            let mut completed = true;
+            for player_move in &moves {
+                if scratch.apply_move(*player_move).is_err() {
+                    continue;
+                }
+                let value = self.alpha_beta(&mut scratch, depth - 1, best_value, i32::MAX);
+                scratch.undo();
+                let Some(value) = value else {
+                    completed = false;
+                    break;
+                };
+                if value > best_value {
+                    best_value = value;
+                    depth_best = *player_move;
+                }
+            }
+            if !completed {
+                break;
+            }
+            best_move = depth_best;
+            if !self.cut_off {
+                break;
+            }
+        }
+
+        best_move
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Direction::{Horizontal, Vertical};
+
+    fn position(height: u16, width: u16, moves: &[Move]) -> GameState {
+        let mut state = GameState::new(height, width, 2);
+        for player_move in moves {
+            state.apply_move(*player_move).unwrap();
+        }
+
+        state
+    }
+
+    /// A row of four boxes with every horizontal edge and both ends drawn, so
+    /// any move leads to nothing but captures.
+    fn chain() -> GameState {
+        let mut moves: Vec<Move> = (0..2)
+            .flat_map(|y| (0..4).map(move |x| Move::new(y, x, Horizontal)))
+            .collect();
+        moves.extend([Move::new(0, 0, Vertical), Move::new(0, 4, Vertical)]);
+
+        position(2, 5, &moves)
+    }
+
+    #[test]
+    fn greedy_bot_takes_an_available_box() {
+        let state = position(
+            3,
+            3,
+            &[
+                Move::new(1, 1, Horizontal),
+                Move::new(2, 1, Horizontal),
+                Move::new(1, 2, Vertical),
+            ],
+        );
+        for seed in 0..10 {
+            let mut bot = BotKind::Greedy.build(Rng::new(seed));
+            assert_eq!(bot.choose_move(&state), Move::new(1, 1, Vertical));
+        }
+    }
+
+    #[test]
+    fn search_bot_avoids_a_third_side_while_safe_moves_remain() {
+        let state = position(
+            3,
+            3,
+            &[
+                Move::new(0, 0, Horizontal),
+                Move::new(1, 0, Horizontal),
+                Move::new(1, 2, Vertical),
+                Move::new(2, 1, Horizontal),
+            ],
+        );
+        for seed in 0..5 {
+            let player_move = SearchBot::new(Rng::new(seed)).choose_move(&state);
+            assert!(!state.gives_third_side(player_move), "{player_move}");
+        }
+    }
+
+    #[test]
+    fn moves_repeat_for_a_seed() {
+        for kind in [BotKind::Random, BotKind::Greedy, BotKind::Hard] {
+            let play = |seed| {
+                let mut bots = [kind.build(Rng::new(seed)), kind.build(Rng::new(seed + 1))];
+                let mut state = GameState::new(3, 3, 2);
+                let mut moves = Vec::new();
+                while !state.is_over() {
+                    let bot = &mut bots[(state.current_player() - 1) as usize];
+                    let player_move = bot.choose_move(&state);
+                    state.apply_move(player_move).unwrap();
+                    moves.push(player_move);
+                }
+                moves
+            };
+            assert_eq!(play(5), play(5), "{kind}");
+        }
+    }
+
+    #[test]
+    fn search_stops_deepening_once_the_whole_tree_is_searched() {
+        let state = chain();
+        let mut bot = SearchBot::new(Rng::new(1));
+        bot.choose_move(&state);
+
+        // A single pass at depth 1 already reaches the end of every line
+        let mut single = SearchBot::new(Rng::new(1));
+        single.root_player = state.current_player();
+        let mut scratch = state.clone();
+        let mut best_value = i32::MIN;
+        for player_move in ordered_moves(&state) {
+            scratch.apply_move(player_move).unwrap();
+            let value = single.alpha_beta(&mut scratch, 0, best_value, i32::MAX);
+            scratch.undo();
+            best_value = best_value.max(value.unwrap());
+        }
+        assert!(!single.cut_off);
+        assert_eq!(bot.nodes, single.nodes);
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::{self, stdin, stdout, BufRead, BufReader, Write};
+use std::net::TcpStream;
+
+use crate::error::BoxesError;
+
+/// The settings for `boxes connect [--watch] host:port [command]`.
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub address: String,
+    /// A lobby command to send on connecting, such as `join 2` or `list`.
+    /// Without one the lobby is used interactively.
+    pub command: Option<String>,
+}
+
+impl Client {
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Client, BoxesError> {
+        let mut options: Vec<String> = args.skip(2).collect();
+        // --watch is short for the watch command
+        let watch = options.first().is_some_and(|option| option == "--watch");
+        if watch {
+            options.remove(0);
+        }
+        let Some((address, command)) = options.split_first() else {
+            return Err(BoxesError::Usage);
+        };
+        let command = match (watch, command.is_empty()) {
+            (true, true) => Some("watch".to_string()),
+            (false, false) => Some(command.join(" ")),
+            (false, true) => None,
+            (true, false) => return Err(BoxesError::Usage),
+        };
+
+        Ok(Client {
+            address: address.clone(),
+            command,
+        })
+    }
+}
+
+/// Connects to a server started with `boxes serve`. Lobby commands are read
+/// from stdin until the client joins or watches a game, unless one was given
+/// on the command line. In a game, everything the server sends is printed
+/// and a move is read from stdin whenever it is this player's turn.
+///
+/// Returns the final standings, or the reply to a lobby command given on the
+/// command line that did not lead to a game.
+pub fn run(client: Client) -> Result<String, BoxesError> {
+    let stream = TcpStream::connect(&client.address).map_err(BoxesError::Network)?;
+    let mut writer = stream.try_clone().map_err(BoxesError::Network)?;
+    let one_shot = client.command.is_some();
+    match client.command {
+        Some(command) => writeln!(writer, "{command}").map_err(BoxesError::Network)?,
+        None => {
+            println!("Lobby commands: list, create [options] height width playercount, join [game] [token], watch [game], archive, history game");
+            let command = read_line("lobby> ")?;
+            writeln!(writer, "{command}").map_err(BoxesError::Network)?;
+        }
+    }
+    let mut in_lobby = true;
+    let mut replies: Vec<String> = Vec::new();
+    let mut seat: Option<u8> = None;
+    let mut own_symbol = String::new();
+    let mut results: Vec<String> = Vec::new();
+
+    for line in BufReader::new(stream).lines() {
+        let line = line.map_err(BoxesError::Network)?;
+        let (kind, text) = line.split_once(' ').unwrap_or((&line, ""));
+        match kind {
+            "game" => replies.push(format!("Game {text}")),
+            "archived" => replies.push(format!("Finished game {text}")),
+            "history" => replies.push(text.to_string()),
+            "created" => replies.push(format!("Created game {text}")),
+            "ok" | "error" if in_lobby => {
+                if kind == "error" {
+                    if one_shot {
+                        return Err(BoxesError::Rejected(text.to_string()));
+                    }
+                    eprintln!("{text}");
+                }
+                if one_shot {
+                    return Ok(replies.join("\n"));
+                }
+                for reply in replies.drain(..) {
+                    println!("{reply}");
+                }
+                let command = read_line("lobby> ")?;
+                writeln!(writer, "{command}").map_err(BoxesError::Network)?;
+            }
+            "watching" => {
+                in_lobby = false;
+                println!("Watching game {text}");
+            }
+            "seat" => {
+                in_lobby = false;
+                let fields: Vec<&str> = text.splitn(5, ' ').collect();
+                let [game, player, symbol, token, name] = fields[..] else {
+                    continue;
+                };
+                seat = player.parse().ok();
+                own_symbol = symbol.to_string();
+                println!("You are player {}", label(name, symbol));
+                println!("To take your seat back after a disconnect: join {game} {token}");
+            }
+            "board" | "scores" | "message" => println!("{text}"),
+            "error" => eprintln!("{text}"),
+            "played" => {
+                // Our own moves were already typed at the prompt
+                let fields: Vec<&str> = text.splitn(5, ' ').collect();
+                if let [symbol, y, x, direction, name] = fields[..] {
+                    if symbol != own_symbol {
+                        println!("{}> {y} {x} {direction}", label(name, symbol));
+                    }
+                }
+                println!();
+            }
+            "turn" => {
+                let fields: Vec<&str> = text.splitn(3, ' ').collect();
+                let [player, symbol, name] = fields[..] else {
+                    continue;
+                };
+                if player.parse().ok() != seat {
+                    println!("Waiting for {name}");
+                    continue;
+                }
+                let player_move = read_line(&format!("{}> ", label(name, symbol)))?;
+                writeln!(writer, "move {player_move}").map_err(BoxesError::Network)?;
+            }
+            "result" => results.push(text.to_string()),
+            "over" => return Ok(results.join("\n")),
+            _ => (),
+        }
+    }
+
+    Err(BoxesError::Network(io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        "the server closed the connection",
+    )))
+}
+
+/// How a player is shown, as in a terminal game: their name, followed by
+/// their symbol when the two differ.
+fn label(name: &str, symbol: &str) -> String {
+    if name == symbol {
+        name.to_string()
+    } else {
+        format!("{name} ({symbol})")
+    }
+}
+
+/// Prompts for and reads one line of stdin, without its line ending.
+fn read_line(prompt: &str) -> Result<String, BoxesError> {
+    print!("{prompt}");
+    stdout().flush().map_err(BoxesError::System)?;
+
+    // Zero bytes read means stdin has been closed
+    let mut line = String::new();
+    match stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => Err(BoxesError::EndOfInput),
+        Ok(_) => Ok(line.trim().to_string()),
+    }
+}
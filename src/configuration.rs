@@ -1,175 +1,552 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::ffi::OsStr;
+use std::fs;
 use std::ops::Range;
+use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(Debug)]
+use crate::bot::BotKind;
+use crate::engine::DEFAULT_MOVE_TIME;
+use crate::error::BoxesError;
+use crate::json::Json;
+use crate::save::{self, SaveFormat};
+use crate::state::LoggedMove;
+use crate::width::display_width;
+
+/// The longest player name accepted, in characters.
+const MAX_NAME_LENGTH: usize = 32;
+
+#[derive(Debug, Clone)]
 pub struct Config {
     pub height: u16,
     pub width: u16,
     pub player_count: u8,
     pub player_turn: u8,
+    pub players: Vec<Player>,
     pub board_edges: Vec<String>,
     pub board_cells: Vec<String>,
+    /// The moves that led to the saved board, oldest first.
+    pub move_log: Vec<LoggedMove>,
+    pub rules: Rules,
+    /// The hints each player has asked for, indexed from player 1.
+    pub hints_used: Vec<u32>,
+    /// The save format chosen with `--format`. Without it the format is
+    /// picked from each file's extension.
+    pub save_format: Option<SaveFormat>,
+    /// The seed for computer players, so games can be repeated exactly.
+    pub seed: Option<u64>,
+    /// The time engine players get for each move.
+    pub move_time: Duration,
+    /// Whether to play in the full-screen interface chosen with `--tui`
+    /// rather than by typing moves line by line.
+    pub tui: bool,
+    /// When the board is drawn in colour, chosen with `--color`.
+    pub colour: ColourMode,
 }
 
-impl Config {
-    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, u8> {
-        // Convert args to vector of strings
-        let options: Vec<String> = args.skip(1).collect();
+/// Details of a single player slot. Player 1 is stored first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Player {
+    pub name: String,
+    /// What marks the player's boxes on the board, one or two columns wide.
+    pub symbol: String,
+    pub kind: PlayerKind,
+}
 
-        // Check argument length
-        if options.len() != 3 && options.len() != 4 {
-            println!("options.len {}", options.len());
-            return Err(1);
+/// Who chooses the moves for a player slot.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum PlayerKind {
+    /// Moves are typed at the terminal.
+    #[default]
+    Human,
+    /// Moves are chosen by a built-in computer player.
+    Bot(BotKind),
+    /// Moves are chosen by an external program run with this command. Saves
+    /// do not keep the command, so the seat is human when the game is loaded.
+    Engine(String),
+}
+
+/// Optional rules chosen when a game is started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    /// Whether players may take back and replay moves with `u` and `r`.
+    pub allow_undo: bool,
+    /// Whether players may ask for a suggested move with `?`.
+    pub allow_hints: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            allow_undo: true,
+            allow_hints: true,
         }
+    }
+}
 
-        // Parse height argument
-        let Ok(height) = options[0].parse::<u16>() else {
-            return Err(2);
-        };
+/// When to draw the board in colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColourMode {
+    /// Only when stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
 
-        // Parse width argument
-        let Ok(width) = options[1].parse::<u16>() else {
-            return Err(2);
-        };
+impl ColourMode {
+    /// Whether to use colour, given whether stdout is a terminal and the
+    /// value of `NO_COLOR`, which only counts when it is set to something.
+    pub fn resolve(self, is_terminal: bool, no_color: Option<&OsStr>) -> bool {
+        match self {
+            ColourMode::Always => true,
+            ColourMode::Never => false,
+            ColourMode::Auto => is_terminal && no_color.is_none_or(OsStr::is_empty),
+        }
+    }
+}
 
-        // Ensure height and width are within valid range
-        let valid_board_dimensions: Range<u16> = 2..1000;
-        if !valid_board_dimensions.contains(&height) || !valid_board_dimensions.contains(&width) {
-            return Err(2);
-        };
+impl FromStr for ColourMode {
+    type Err = ();
 
-        // Parse player_count argument
-        let Ok(player_count) = options[2].parse::<u8>() else {
-            return Err(3);
-        };
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColourMode::Auto),
+            "always" => Ok(ColourMode::Always),
+            "never" => Ok(ColourMode::Never),
+            _ => Err(()),
+        }
+    }
+}
 
-        // Validate number of players - Minimum of 2, only allow chars up to 'Z'
-        let valid_player_count: Range<u8> = 2..101;
-        if !valid_player_count.contains(&player_count) {
-            return Err(3);
-        };
+impl Rules {
+    /// Applies a rule flag from the command line, returning false if the flag
+    /// is not a rule.
+    fn apply_flag(&mut self, flag: &str) -> bool {
+        match flag {
+            "--no-undo" => self.allow_undo = false,
+            "--no-hints" => self.allow_hints = false,
+            _ => return false,
+        }
+
+        true
+    }
+}
 
-        // Initialise struct
-        let mut config: Config = Config {
+impl Config {
+    /// Creates the configuration for a new game with default players and rules.
+    pub fn new(height: u16, width: u16, player_count: u8) -> Config {
+        let players = (1..player_count + 1)
+            .map(|player| Player {
+                name: default_player_symbol(player),
+                symbol: default_player_symbol(player),
+                kind: PlayerKind::Human,
+            })
+            .collect();
+
+        Config {
             height,
             width,
             player_count,
             player_turn: 1,
+            players,
             board_edges: Vec::new(),
             board_cells: Vec::new(),
-        };
+            move_log: Vec::new(),
+            rules: Rules::default(),
+            hints_used: vec![0; player_count as usize],
+            save_format: None,
+            seed: None,
+            move_time: DEFAULT_MOVE_TIME,
+            tui: false,
+            colour: ColourMode::default(),
+        }
+    }
 
-        // Return early if save game wasn't given
-        if options.len() == 3 {
-            Ok(config)
-        } else {
-            let Ok(file_content) = File::open(&options[3]) else {
-                return Err(4);
-            };
-
-            // Attempt to read lines into a vector of strings
-            let Ok(lines) = BufReader::new(&file_content)
-                .lines()
-                .collect::<Result<Vec<String>, _>>() else {
-                return Err(5);
-            };
-
-            // Parse player turn
-            let Ok(player_turn) = lines[0].parse::<u8>() else {
-                return Err(5);
-            };
-            let valid_player_turns: Range<u8> = 1..101;
-            if !valid_player_turns.contains(&player_turn) {
-                return Err(5);
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, BoxesError> {
+        // Separate flags from positional arguments
+        let mut options: Vec<String> = Vec::new();
+        let mut flags: Vec<String> = Vec::new();
+        let mut save_format: Option<SaveFormat> = None;
+        let mut bots: Vec<(String, PlayerKind)> = Vec::new();
+        let mut seed: Option<u64> = None;
+        let mut move_time: Option<Duration> = None;
+        let mut tui = false;
+        let mut colour = ColourMode::default();
+        let mut players_file: Option<String> = None;
+        let mut names: Vec<(String, String)> = Vec::new();
+        let mut symbols: Vec<(String, String)> = Vec::new();
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => {
+                    let format = args.next().and_then(|format| format.parse().ok());
+                    save_format = Some(format.ok_or(BoxesError::Usage)?);
+                }
+                "--bot" => {
+                    // Written as player=kind, for example B=greedy
+                    let bot = args.next().and_then(|bot| {
+                        let (player, kind) = bot.split_once('=')?;
+                        let kind = kind.parse::<BotKind>().ok()?;
+                        Some((player.to_string(), PlayerKind::Bot(kind)))
+                    });
+                    bots.push(bot.ok_or(BoxesError::Usage)?);
+                }
+                "--engine" => {
+                    // Written as player=command, for example B="./engine --fast"
+                    let engine = args.next().and_then(|engine| {
+                        let (player, command) = engine.split_once('=')?;
+                        let command = command.trim();
+                        (!command.is_empty())
+                            .then(|| (player.to_string(), PlayerKind::Engine(command.to_string())))
+                    });
+                    bots.push(engine.ok_or(BoxesError::Usage)?);
+                }
+                "--move-time" => {
+                    let value = args.next().and_then(|value| value.parse::<u64>().ok());
+                    let value = value.filter(|millis| *millis > 0);
+                    move_time = Some(Duration::from_millis(value.ok_or(BoxesError::Usage)?));
+                }
+                "--seed" => {
+                    let value = args.next().and_then(|value| value.parse::<u64>().ok());
+                    seed = Some(value.ok_or(BoxesError::Usage)?);
+                }
+                "--tui" => tui = true,
+                "--players" => players_file = Some(args.next().ok_or(BoxesError::Usage)?),
+                // Written as player=value, for example B=Alice or B=@
+                "--name" | "--symbol" => {
+                    let value = args.next().and_then(|value| {
+                        let (player, value) = value.split_once('=')?;
+                        Some((player.to_string(), value.trim().to_string()))
+                    });
+                    let value = value.ok_or(BoxesError::Usage)?;
+                    if arg == "--name" {
+                        names.push(value);
+                    } else {
+                        symbols.push(value);
+                    }
+                }
+                "--color" => {
+                    let mode = args.next().and_then(|mode| mode.parse().ok());
+                    colour = mode.ok_or(BoxesError::Usage)?;
+                }
+                flag if flag.starts_with("--") => flags.push(arg),
+                _ => options.push(arg),
             }
-            config.player_turn = player_turn;
-
-            // Parse board edges and cells
-            for line in lines.iter().skip(1) {
-                if !line.contains(',') {
-                    config.board_edges.push(line.to_string());
-                } else {
-                    config.board_cells.push(line.to_string());
+        }
+
+        // A versioned save holds everything needed to resume on its own
+        let mut config = match options.len() {
+            1 => save::load(&options[0], None, save_format)?,
+            3 | 4 => {
+                let dimensions = parse_dimensions(&options[..3])?;
+                match options.get(3) {
+                    Some(path) => save::load(path, Some(dimensions), save_format)?,
+                    None => Config::new(dimensions.0, dimensions.1, dimensions.2),
                 }
             }
+            _ => return Err(BoxesError::Usage),
+        };
+
+        config.save_format = save_format;
+        config.seed = seed;
+        config.move_time = move_time.unwrap_or(DEFAULT_MOVE_TIME);
+        config.tui = tui;
+        config.colour = colour;
+
+        // A players file and then the command line override names and symbols
+        if let Some(path) = players_file {
+            config.apply_players_file(&path)?;
+        }
+        for (player, name) in names {
+            let player = config.find_player(&player)?;
+            config.players[(player - 1) as usize].name = name;
+        }
+        for (player, symbol) in symbols {
+            let player = config.find_player(&player)?;
+            config.players[(player - 1) as usize].symbol = symbol;
+        }
+        check_players(&config.players)?;
+
+        // Bots and engines given on the command line replace whoever held the slot
+        for (player, kind) in bots {
+            let player = config.find_player(&player)?;
+            config.players[(player - 1) as usize].kind = kind;
+        }
+
+        // Rules given on the command line take priority over those in a save
+        for flag in &flags {
+            if !config.rules.apply_flag(flag) {
+                return Err(BoxesError::Usage);
+            }
+        }
 
-            // Validate the save file contents
-            if !valid_edge_data(&config.board_edges, config.height, config.width)
-                || !valid_cell_data(
-                    &config.board_cells,
-                    config.height,
-                    config.width,
-                    config.player_count,
-                )
-            {
-                return Err(5);
+        Ok(config)
+    }
+
+    /// Finds a player from a number, their default symbol, their symbol or
+    /// their name, such as `2`, `B`, `@` or `Alice`.
+    pub fn player_number(&self, player: &str) -> Option<u8> {
+        let number = match player.parse::<u8>() {
+            Ok(number) => number,
+            Err(_) => (1..=self.player_count).find(|p| {
+                let details = &self.players[(p - 1) as usize];
+                default_player_symbol(*p) == player
+                    || details.symbol == player
+                    || details.name == player
+            })?,
+        };
+
+        (1..=self.player_count).contains(&number).then_some(number)
+    }
+
+    /// Like [`Config::player_number`], for players named on the command line.
+    fn find_player(&self, player: &str) -> Result<u8, BoxesError> {
+        self.player_number(player)
+            .ok_or_else(|| BoxesError::Player(format!("there is no player {player}")))
+    }
+
+    /// Reads names and symbols from a JSON file laid out like the players of
+    /// a JSON save, `{"players": [{"name": "...", "symbol": "..."}]}`, giving
+    /// them to players in order. Either field may be left out.
+    fn apply_players_file(&mut self, path: &str) -> Result<(), BoxesError> {
+        let invalid = |reason: String| BoxesError::Player(format!("{path}: {reason}"));
+        let text = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let document = Json::parse(&text).map_err(|e| invalid(e.to_string()))?;
+        let entries = document
+            .get("players")
+            .and_then(Json::as_array)
+            .ok_or_else(|| invalid("expected an object with a players array".to_string()))?;
+        if entries.len() > self.players.len() {
+            return Err(invalid(format!(
+                "{} players listed for a {} player game",
+                entries.len(),
+                self.players.len()
+            )));
+        }
+
+        for (index, (entry, player)) in entries.iter().zip(&mut self.players).enumerate() {
+            for (field, value) in [("name", &mut player.name), ("symbol", &mut player.symbol)] {
+                match entry.get(field) {
+                    None => (),
+                    Some(Json::String(text)) => *value = text.trim().to_string(),
+                    Some(_) => {
+                        return Err(invalid(format!(
+                            "players[{index}].{field} must be a string"
+                        )))
+                    }
+                }
             }
+        }
+
+        Ok(())
+    }
 
-            Ok(config)
+    /// Builds a configuration for the commands that take a save, such as
+    /// `boxes replay <file>`.
+    pub fn build_replay(args: impl Iterator<Item = String>) -> Result<Config, BoxesError> {
+        let options: Vec<String> = args.skip(2).collect();
+        if options.len() != 1 {
+            return Err(BoxesError::Usage);
         }
+
+        save::load_replay(&options[0])
     }
 }
 
-fn valid_edge_data(edge_data: &[String], height: u16, width: u16) -> bool {
-    let mut row_data_count: u16 = 0;
-    let mut col_data_count: u16 = 0;
+fn parse_dimensions(options: &[String]) -> Result<(u16, u16, u8), BoxesError> {
+    // Parse height argument
+    let Ok(height) = options[0].parse::<u16>() else {
+        return Err(BoxesError::Dimensions);
+    };
 
-    // Validate edge position data against game board
-    for (row, data) in edge_data.iter().enumerate() {
-        // From idx 0, every second line should be of size width - 1
-        if row % 2 == 0 {
-            row_data_count += 1;
-            if width as usize - 1 != data.len() {
-                return false;
-            }
-        // From idx 1, every second line should be of size width
-        } else {
-            col_data_count += 1;
-            if width as usize != data.len() {
-                return false;
-            }
+    // Parse width argument
+    let Ok(width) = options[1].parse::<u16>() else {
+        return Err(BoxesError::Dimensions);
+    };
+
+    // Ensure height and width are within valid range
+    if !valid_board_dimension(height) || !valid_board_dimension(width) {
+        return Err(BoxesError::Dimensions);
+    };
+
+    // Parse player_count argument
+    let Ok(player_count) = options[2].parse::<u8>() else {
+        return Err(BoxesError::PlayerCount);
+    };
+
+    // Validate number of players - Minimum of 2, maximum of 100
+    if !valid_player_count(player_count) {
+        return Err(BoxesError::PlayerCount);
+    };
+
+    Ok((height, width, player_count))
+}
+
+/// Checks that every name is printable and not a number, and that symbols
+/// are distinct and fit a box on the board.
+pub(crate) fn check_players(players: &[Player]) -> Result<(), BoxesError> {
+    for (index, player) in players.iter().enumerate() {
+        let number = index + 1;
+        if !valid_player_name(&player.name) {
+            return Err(BoxesError::Player(format!(
+                "player {number} needs a name of 1 to {MAX_NAME_LENGTH} printable characters that is not a number"
+            )));
+        }
+        if !valid_player_symbol(&player.symbol) {
+            return Err(BoxesError::Player(format!(
+                "the symbol of player {number} must be one or two columns wide without spaces"
+            )));
+        }
+        if players[..index]
+            .iter()
+            .any(|other| other.symbol == player.symbol)
+        {
+            return Err(BoxesError::Player(format!(
+                "symbol {} is used by more than one player",
+                player.symbol
+            )));
         }
     }
 
-    // Validate amount of row and column data against game board
-    if row_data_count != height || col_data_count != width - 1 {
-        return false;
+    Ok(())
+}
+
+/// Names are printable. A name of digits alone is refused, as it could not be
+/// told apart from a player number in `--name` and `--symbol`.
+pub(crate) fn valid_player_name(name: &str) -> bool {
+    !name.trim().is_empty()
+        && name.chars().count() <= MAX_NAME_LENGTH
+        && !name.chars().any(char::is_control)
+        && !name.chars().all(|character| character.is_ascii_digit())
+}
+
+/// A symbol is a single character or a short glyph such as an emoji, made of
+/// at most a handful of characters and drawn one or two columns wide.
+pub(crate) fn valid_player_symbol(symbol: &str) -> bool {
+    (1..=2).contains(&display_width(symbol))
+        && symbol.chars().count() <= 8
+        && !symbol
+            .chars()
+            .any(|character| character.is_whitespace() || character.is_control())
+}
+
+pub(crate) fn valid_board_dimension(dots: u16) -> bool {
+    let valid_board_dimensions: Range<u16> = 2..1000;
+    valid_board_dimensions.contains(&dots)
+}
+
+pub(crate) fn valid_player_count(player_count: u8) -> bool {
+    let valid_player_count: Range<u8> = 2..101;
+    valid_player_count.contains(&player_count)
+}
+
+/// Names players like spreadsheet columns: `A` to `Z`, then `AA`, `AB` and so on.
+pub fn default_player_symbol(player: u8) -> String {
+    let mut symbol = String::new();
+    let mut remaining = player as u32;
+    while remaining > 0 {
+        let letter = (remaining - 1) % 26;
+        symbol.insert(0, char::from(b'A' + letter as u8));
+        remaining = (remaining - 1) / 26;
     }
 
-    true
+    symbol
 }
 
-fn valid_cell_data(cell_data: &[String], height: u16, width: u16, player_count: u8) -> bool {
-    let mut rows: u16 = 0;
-    let valid_cell_entries: Range<u8> = 0..101;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(args: &str) -> Result<Config, BoxesError> {
+        Config::build(args.split(' ').map(String::from))
+    }
+
+    #[test]
+    fn bots_and_engines_go_to_the_named_player() {
+        let config = build("boxes --bot B=greedy --engine 1=./engine 3 3 2").unwrap();
 
-    // Parse each player cell data
-    for data in cell_data {
-        rows += 1;
-        // Remove commas and store complete numbers in vector
-        let split_string: Vec<&str> = data.split(',').collect();
-        // Check that cells in save can fit on game board width
-        if width as usize - 1 != split_string.len() {
-            return false;
+        assert!(
+            matches!(config.players[0].kind, PlayerKind::Engine(ref command) if command == "./engine")
+        );
+        assert_eq!(config.players[1].kind, PlayerKind::Bot(BotKind::Greedy));
+    }
+
+    #[test]
+    fn bots_for_unknown_players_are_refused() {
+        for args in [
+            "boxes --bot C=greedy 3 3 2",
+            "boxes --engine 3=./engine 3 3 2",
+        ] {
+            assert!(
+                matches!(build(args), Err(BoxesError::Player(ref reason)) if reason.starts_with("there is no player")),
+                "{args}"
+            );
         }
-        for player in split_string {
-            // Attempt to convert the player cell entry to numeric
-            let Ok(player_as_numeric) = player.parse::<u8>() else {
-                return false;
-            };
-            // Ensure numbers are within valid range and the number of players is less than or equal to the player count
-            if !valid_cell_entries.contains(&player_as_numeric) || player_as_numeric > player_count
-            {
-                return false;
-            }
+    }
+
+    #[test]
+    fn accepts_printable_names_that_are_not_numbers() {
+        for name in [
+            "Alice",
+            "Player 2",
+            "R2D2",
+            "Zoë",
+            "李雷",
+            "🦀 Ferris",
+            &"x".repeat(32),
+        ] {
+            assert!(valid_player_name(name), "{name:?}");
+        }
+        for name in ["", "   ", "42", "tab\there", "new\nline", &"x".repeat(33)] {
+            assert!(!valid_player_name(name), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn accepts_symbols_one_or_two_columns_wide() {
+        for symbol in [
+            "X",
+            "@",
+            "7",
+            "é",
+            "e\u{301}",
+            "猫",
+            "🦀",
+            "👍🏽",
+            "❤\u{fe0f}",
+            "👩\u{200d}💻",
+        ] {
+            assert!(valid_player_symbol(symbol), "{symbol:?}");
+        }
+        for symbol in ["", " ", "ABC", "猫猫", "🦀🦀", "\u{301}", "\t", "a b"] {
+            assert!(!valid_player_symbol(symbol), "{symbol:?}");
         }
+        // A family emoji is drawn as one glyph but is made of too many characters
+        let family = "👨\u{200d}👩\u{200d}👧\u{200d}👦\u{200d}👦";
+        assert_eq!(display_width(family), 2);
+        assert!(!valid_player_symbol(family));
     }
 
-    // Check that cells in save can fit on game board height
-    if height - 1 != rows {
-        return false;
+    #[test]
+    fn default_symbols_count_like_spreadsheet_columns() {
+        let symbols: Vec<String> = [1, 2, 26, 27, 28, 52, 53, 100]
+            .into_iter()
+            .map(default_player_symbol)
+            .collect();
+
+        assert_eq!(symbols, ["A", "B", "Z", "AA", "AB", "AZ", "BA", "CV"]);
     }
 
-    true
+    #[test]
+    fn colour_follows_the_terminal_and_no_color() {
+        let set = Some(OsStr::new("1"));
+        let empty = Some(OsStr::new(""));
+
+        assert!(ColourMode::Auto.resolve(true, None));
+        assert!(ColourMode::Auto.resolve(true, empty));
+        assert!(!ColourMode::Auto.resolve(true, set));
+        assert!(!ColourMode::Auto.resolve(false, None));
+        assert!(ColourMode::Always.resolve(false, set));
+        assert!(!ColourMode::Never.resolve(true, None));
+    }
 }
@@ -0,0 +1,420 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, stdin, stdout, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bot::BotKind;
+use crate::configuration::{valid_board_dimension, valid_player_count, Config};
+use crate::error::BoxesError;
+use crate::rng::Rng;
+use crate::save::{cell_rows, edge_rows};
+use crate::state::{GameState, Move};
+
+/// The protocol version sent in the handshake.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The time an engine gets for each move unless `--move-time` says otherwise.
+pub const DEFAULT_MOVE_TIME: Duration = Duration::from_millis(1000);
+
+/// The time an engine gets to start up and answer `boxes` with `ready`.
+const HANDSHAKE_TIME: Duration = Duration::from_secs(5);
+
+/// Extra time allowed on top of the move time for the pipes and the
+/// operating system, so an engine that uses its full time is not forfeited.
+const TIME_GRACE: Duration = Duration::from_millis(100);
+
+/// Why an engine could not be started or lost its game.
+#[derive(Debug)]
+pub enum EngineError {
+    /// The program could not be run.
+    Start(io::Error),
+    /// The program did not answer `boxes` with `ready` in time.
+    Handshake,
+    /// The program exited or closed its output.
+    Crashed,
+    /// No move arrived within the move time.
+    Timeout,
+    /// The reply to `go` was not a legal move.
+    IllegalMove(String),
+    /// The program answered `go` with `error`.
+    Rejected(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::Start(_) => write!(f, "could not run the engine program"),
+            EngineError::Handshake => write!(f, "no reply to the handshake"),
+            EngineError::Crashed => write!(f, "the engine exited"),
+            EngineError::Timeout => write!(f, "out of time"),
+            EngineError::IllegalMove(reply) => write!(f, "illegal move '{reply}'"),
+            EngineError::Rejected(message) => write!(f, "engine error '{message}'"),
+        }
+    }
+}
+
+impl Error for EngineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EngineError::Start(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A player that runs as a separate program, spoken to over a line based
+/// protocol in the spirit of UCI and GTP. `boxes` starts the program, writes
+/// commands to its stdin and reads replies from its stdout, one per line:
+///
+/// - `boxes 1` opens the session with the protocol version. The engine may
+///   answer with `id name <name>` and must finish with `ready`.
+/// - `position <height> <width> <players> <to move> <edges> <cells>` sets up
+///   a board, with the edge and cell rows of a text save joined by `/`.
+/// - `play y x h|v` tells the engine about a move made since the last
+///   `position` or `play`, including its own.
+/// - `go <ms>` asks for a move within the time given. The engine answers
+///   `move y x h|v` without playing it, and hears it back as `play` with
+///   the moves that follow.
+/// - `quit` ends the session.
+///
+/// Engines may write `info` lines at any time, which are ignored. A reply of
+/// `error <message>` to `go` forfeits the game, as do an illegal move, no
+/// move in time and the program exiting.
+pub struct Engine {
+    child: Child,
+    input: ChildStdin,
+    output: Receiver<String>,
+    name: String,
+    move_time: Duration,
+    /// The moves the engine has been told about, so later turns only need
+    /// to send the moves made since. `None` until a position has been sent.
+    known: Option<Vec<Move>>,
+}
+
+impl Engine {
+    /// Runs `command`, split on whitespace into a program and its arguments,
+    /// and completes the handshake.
+    pub fn start(command: &str, move_time: Duration) -> Result<Engine, EngineError> {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().ok_or_else(|| {
+            EngineError::Start(io::Error::new(io::ErrorKind::InvalidInput, "empty command"))
+        })?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(EngineError::Start)?;
+        let input = child.stdin.take().expect("stdin is piped");
+        let reader = child.stdout.take().expect("stdout is piped");
+
+        // Lines are read on their own thread so waiting for one can time out
+        let (sender, output) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Engine {
+            child,
+            input,
+            output,
+            name: program.to_string(),
+            move_time,
+            known: None,
+        };
+        engine.send(&format!("boxes {PROTOCOL_VERSION}"))?;
+        let deadline = Instant::now() + HANDSHAKE_TIME;
+        loop {
+            let line = match engine.read_line(deadline) {
+                Err(EngineError::Timeout) => return Err(EngineError::Handshake),
+                result => result?,
+            };
+            if line.trim() == "ready" {
+                break;
+            }
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+        }
+
+        Ok(engine)
+    }
+
+    /// The name the engine gave in the handshake, or its program name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Brings the engine up to date with the game and asks it for a move. The
+    /// move is checked but not played.
+    pub fn choose_move(&mut self, state: &GameState) -> Result<Move, EngineError> {
+        self.sync(state)?;
+        self.send(&format!("go {}", self.move_time.as_millis()))?;
+
+        let deadline = Instant::now() + self.move_time + TIME_GRACE;
+        loop {
+            let line = self.read_line(deadline)?;
+            if let Some(reply) = line.strip_prefix("move ") {
+                let reply = reply.trim();
+                return match reply.parse::<Move>() {
+                    Ok(player_move) if state.check_move(player_move).is_ok() => Ok(player_move),
+                    _ => Err(EngineError::IllegalMove(reply.to_string())),
+                };
+            }
+            if let Some(message) = line.strip_prefix("error") {
+                return Err(EngineError::Rejected(message.trim().to_string()));
+            }
+        }
+    }
+
+    /// Sends the moves played since the engine last heard from the game, or
+    /// the whole position when moves it knew about have been undone.
+    fn sync(&mut self, state: &GameState) -> Result<(), EngineError> {
+        let history: Vec<Move> = state.history().iter().map(|r| r.player_move).collect();
+        match &self.known {
+            Some(known) if history.starts_with(known) => {
+                for player_move in &history[known.len()..] {
+                    self.send(&format!("play {player_move}"))?;
+                }
+            }
+            _ => {
+                let position = format!(
+                    "position {} {} {} {} {} {}",
+                    state.height(),
+                    state.width(),
+                    state.player_count(),
+                    state.current_player(),
+                    edge_rows(state).join("/"),
+                    cell_rows(state).join("/")
+                );
+                self.send(&position)?;
+            }
+        }
+        self.known = Some(history);
+
+        Ok(())
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), EngineError> {
+        writeln!(self.input, "{line}")
+            .and_then(|_| self.input.flush())
+            .map_err(|_| EngineError::Crashed)
+    }
+
+    fn read_line(&self, deadline: Instant) -> Result<String, EngineError> {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        self.output.recv_timeout(wait).map_err(|e| match e {
+            RecvTimeoutError::Timeout => EngineError::Timeout,
+            RecvTimeoutError::Disconnected => EngineError::Crashed,
+        })
+    }
+}
+
+impl Drop for Engine {
+    /// Asks the engine to quit and stops it if it has not after a moment.
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        for _ in 0..10 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The settings for `boxes engine`, which plays a built-in bot over the
+/// protocol on stdin and stdout.
+#[derive(Debug, Clone)]
+pub struct EngineMode {
+    pub bot: BotKind,
+    pub seed: Option<u64>,
+}
+
+impl EngineMode {
+    /// Builds the settings for `boxes engine [--seed n] [bot]`. The hard bot
+    /// plays when none is named.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<EngineMode, BoxesError> {
+        let mut mode = EngineMode {
+            bot: BotKind::Hard,
+            seed: None,
+        };
+        let mut bot = None;
+        let mut args = args.skip(2);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let seed = args.next().and_then(|seed| seed.parse::<u64>().ok());
+                    mode.seed = Some(seed.ok_or(BoxesError::Usage)?);
+                }
+                _ if bot.is_none() => {
+                    bot = Some(arg.parse::<BotKind>().map_err(|_| BoxesError::Usage)?)
+                }
+                _ => return Err(BoxesError::Usage),
+            }
+        }
+        mode.bot = bot.unwrap_or(mode.bot);
+
+        Ok(mode)
+    }
+}
+
+/// Answers protocol commands on stdin until `quit` or the end of input.
+pub fn run(mode: EngineMode) -> Result<String, BoxesError> {
+    let mut bot = mode
+        .bot
+        .build(mode.seed.map_or_else(Rng::from_entropy, Rng::new));
+    let mut state: Option<GameState> = None;
+
+    for line in stdin().lock().lines() {
+        let line = line.map_err(BoxesError::System)?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let reply = match fields.as_slice() {
+            [] => continue,
+            ["quit"] => break,
+            ["boxes", version] if version.parse() == Ok(PROTOCOL_VERSION) => {
+                format!("id name boxes {}\nready", mode.bot)
+            }
+            ["boxes", _] => "error unsupported protocol version".to_string(),
+            ["position", fields @ ..] => match parse_position(fields) {
+                Some(position) => {
+                    state = Some(position);
+                    continue;
+                }
+                None => "error invalid position".to_string(),
+            },
+            ["play", player_move @ ..] => {
+                let played = state.as_mut().and_then(|state| {
+                    let player_move = player_move.join(" ").parse::<Move>().ok()?;
+                    state.apply_move(player_move).ok()
+                });
+                if played.is_some() {
+                    continue;
+                }
+                "error illegal move".to_string()
+            }
+            ["go", ..] => match state.as_ref() {
+                Some(state) if !state.is_over() => format!("move {}", bot.choose_move(state)),
+                _ => "error no move to make".to_string(),
+            },
+            _ => "error unknown command".to_string(),
+        };
+        println!("{reply}");
+        stdout().flush().map_err(BoxesError::System)?;
+    }
+
+    Ok(String::new())
+}
+
+/// Reads the fields after `position`: dimensions, player count, the player
+/// to move and the edge and cell rows of a text save joined by `/`.
+fn parse_position(fields: &[&str]) -> Option<GameState> {
+    let [height, width, player_count, turn, edges, cells] = fields else {
+        return None;
+    };
+    let height = height.parse::<u16>().ok()?;
+    let width = width.parse::<u16>().ok()?;
+    let player_count = player_count.parse::<u8>().ok()?;
+    let turn = turn.parse::<u8>().ok()?;
+    if !valid_board_dimension(height)
+        || !valid_board_dimension(width)
+        || !valid_player_count(player_count)
+        || !(1..=player_count).contains(&turn)
+    {
+        return None;
+    }
+
+    // Rows alternate between horizontal and vertical edges, starting with horizontal
+    let edges: Vec<&str> = edges.split('/').collect();
+    let edges_valid = edges.len() == 2 * height as usize - 1
+        && edges.iter().enumerate().all(|(row, data)| {
+            let length = if row % 2 == 0 { width - 1 } else { width };
+            data.len() == length as usize && data.chars().all(|c| c == '0' || c == '1')
+        });
+    let cells: Vec<&str> = cells.split('/').collect();
+    let cells_valid = cells.len() == height as usize - 1
+        && cells.iter().all(|row| {
+            let owners: Vec<Option<u8>> = row.split(',').map(|o| o.parse::<u8>().ok()).collect();
+            owners.len() == width as usize - 1
+                && owners
+                    .iter()
+                    .all(|owner| owner.is_some_and(|owner| owner <= player_count))
+        });
+    if !edges_valid || !cells_valid {
+        return None;
+    }
+
+    let mut config = Config::new(height, width, player_count);
+    config.player_turn = turn;
+    config.board_edges = edges.iter().map(|row| row.to_string()).collect();
+    config.board_cells = cells.iter().map(|row| row.to_string()).collect();
+
+    Some(GameState::build(&config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Direction;
+
+    fn position(line: &str) -> Option<GameState> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        parse_position(&fields)
+    }
+
+    #[test]
+    fn reads_a_position() {
+        let state = position("3 3 2 2 10/100/00/001/00 0,0/0,0").unwrap();
+
+        assert_eq!((state.height(), state.width()), (3, 3));
+        assert_eq!(state.current_player(), 2);
+        assert!(state.has_edge(Move::new(0, 0, Direction::Horizontal)));
+        assert!(state.has_edge(Move::new(0, 0, Direction::Vertical)));
+        assert!(state.has_edge(Move::new(1, 2, Direction::Vertical)));
+        assert_eq!(state.remaining_edges(), 9);
+    }
+
+    #[test]
+    fn reads_claimed_boxes() {
+        let state = position("2 2 3 3 1/11/1 2").unwrap();
+
+        assert_eq!(state.owner(0, 0), 2);
+        assert_eq!(state.scores(), vec![0, 1, 0]);
+        assert!(state.is_over());
+    }
+
+    #[test]
+    fn rejects_malformed_positions() {
+        for line in [
+            "",
+            "3 3 2 1 10/100/00/001/00",
+            "3 3 2 1 10/100/00/001/00 0,0/0,0 extra",
+            "x 3 2 1 10/100/00/001/00 0,0/0,0",
+            "1 3 2 1 00 0",
+            "3 3 1 1 10/100/00/001/00 0,0/0,0",
+            "3 3 2 0 10/100/00/001/00 0,0/0,0",
+            "3 3 2 3 10/100/00/001/00 0,0/0,0",
+            "3 3 2 1 10/100/00/001 0,0/0,0",
+            "3 3 2 1 10/10/00/001/00 0,0/0,0",
+            "3 3 2 1 10/100/02/001/00 0,0/0,0",
+            "3 3 2 1 10/100/00/001/00 0,0",
+            "3 3 2 1 10/100/00/001/00 0,0/0",
+            "3 3 2 1 10/100/00/001/00 0,0/0,3",
+            "3 3 2 1 10/100/00/001/00 0,0/0,a",
+        ] {
+            assert!(position(line).is_none(), "{line:?}");
+        }
+    }
+}
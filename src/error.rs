@@ -0,0 +1,275 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::engine::EngineError;
+use crate::json::JsonError;
+use crate::solver::SolveError;
+
+/// Errors returned by the `boxes` library.
+#[derive(Debug)]
+pub enum BoxesError {
+    /// The command line arguments did not match the expected usage.
+    Usage,
+    /// The board height or width was missing, malformed or out of range.
+    Dimensions,
+    /// The player count was missing, malformed or out of range.
+    PlayerCount,
+    /// The save file could not be opened or read.
+    SaveIo(io::Error),
+    /// The save file was read but its contents are not a valid grid.
+    SaveParse(SaveParseError),
+    /// Input ended while waiting for a player move.
+    EndOfInput,
+    /// The position cannot be handed to the solver.
+    Solve(SolveError),
+    /// The tournament results file could not be written.
+    Output(io::Error),
+    /// An engine player could not be started.
+    Engine(EngineError),
+    /// A network connection could not be made or was lost.
+    Network(io::Error),
+    /// The server turned down a command with this reason.
+    Rejected(String),
+    /// A player name, symbol or players file was not usable, for this reason.
+    Player(String),
+    /// An operating system call failed during play.
+    System(io::Error),
+}
+
+/// The reason a save file was rejected. Line and column numbers start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveParseError {
+    /// The save file has no content.
+    Empty,
+    /// The first line, which holds the player turn, is blank.
+    MissingTurn,
+    /// The player turn is not a number between 1 and the player count.
+    InvalidTurn {
+        line: usize,
+        found: String,
+        player_count: u8,
+    },
+    /// The file ended before every row of the board was read.
+    MissingRow { line: usize, kind: RowKind },
+    /// A row holds the wrong number of entries for the board width.
+    RowLength {
+        line: usize,
+        kind: RowKind,
+        expected: usize,
+        actual: usize,
+    },
+    /// An edge row holds something other than `0` or `1`.
+    InvalidEdge {
+        line: usize,
+        column: usize,
+        found: char,
+    },
+    /// A cell entry is not a number.
+    InvalidCell {
+        line: usize,
+        column: usize,
+        found: String,
+    },
+    /// A cell is owned by a player number greater than the player count.
+    UnknownPlayer {
+        line: usize,
+        column: usize,
+        player: u16,
+    },
+    /// There are more lines after the last cell row that are not a move log.
+    TrailingData { line: usize },
+    /// A move log entry is not of the form `player y x h|v boxes`.
+    InvalidLogEntry { line: usize, found: String },
+    /// A move log entry does not match the saved board or the turn order.
+    InconsistentLogEntry { line: usize },
+    /// The save was written in a format version this build cannot read.
+    UnsupportedVersion { found: String },
+    /// A header line is unknown or its value is out of range.
+    InvalidHeader { line: usize, found: String },
+    /// A required header line is missing. Legacy saves have no header, so
+    /// they report a missing size when loaded without one.
+    MissingHeader { field: &'static str },
+    /// The header disagrees with the dimensions given on the command line.
+    HeaderMismatch { field: &'static str },
+    /// The line that starts a section of a versioned save is missing.
+    MissingSection { line: usize, section: &'static str },
+    /// The grid of a save loaded without its dimensions does not make a
+    /// board of a valid size. `line` is the row whose length or position
+    /// gave the bad dimension.
+    InvalidGrid {
+        line: usize,
+        height: usize,
+        width: usize,
+    },
+    /// A JSON save is not a valid JSON document.
+    InvalidJson(JsonError),
+    /// A field of a JSON save is missing, has the wrong type or does not fit
+    /// the board. Nested fields are written as `edges.horizontal[2]`.
+    InvalidField { field: String },
+}
+
+/// The rows that make up the grid section of a save file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowKind {
+    HorizontalEdges,
+    VerticalEdges,
+    Cells,
+}
+
+impl fmt::Display for BoxesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoxesError::Usage => write!(f, "Usage: boxes [--no-undo] [--no-hints] [--format text|json] [--bot player=random|greedy|hard|mcts[:n|:nms]] [--seed n] [--engine player=command] [--move-time ms] [--tui] [--color auto|always|never] [--players file] [--name player=name] [--symbol player=symbol] height width playercount [filename] | boxes [options] filename | boxes replay filename | boxes solve|analyze filename | boxes serve [--port n] [--forfeit-after s] [--archive dir] [[options] height width playercount] | boxes connect [--watch] host:port [command] | boxes http [--port n] | boxes engine [--seed n] [bot] | boxes tournament [--mode round-robin|gauntlet] [--size HxW]... [--games n] [--seed n] [--threads n] [--output file] bot bot..."),
+            BoxesError::Dimensions => write!(f, "Invalid grid dimensions"),
+            BoxesError::PlayerCount => write!(f, "Invalid player count"),
+            BoxesError::SaveIo(_) => write!(f, "Invalid grid file"),
+            BoxesError::SaveParse(cause) => write!(f, "Error reading grid contents: {cause}"),
+            BoxesError::EndOfInput => write!(f, "End of user input"),
+            BoxesError::Solve(cause) => write!(f, "Cannot solve position: {cause}"),
+            BoxesError::Output(cause) => write!(f, "Error writing results file: {cause}"),
+            BoxesError::Engine(cause) => write!(f, "Cannot start engine: {cause}"),
+            BoxesError::Network(_) => write!(f, "Network failure"),
+            BoxesError::Rejected(reason) => write!(f, "Server refused: {reason}"),
+            BoxesError::Player(reason) => write!(f, "Invalid player: {reason}"),
+            BoxesError::System(_) => write!(f, "System call failure"),
+        }
+    }
+}
+
+impl Error for BoxesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BoxesError::SaveIo(e)
+            | BoxesError::Output(e)
+            | BoxesError::Network(e)
+            | BoxesError::System(e) => Some(e),
+            BoxesError::SaveParse(cause) => Some(cause),
+            BoxesError::Solve(cause) => Some(cause),
+            BoxesError::Engine(cause) => Some(cause),
+            _ => None,
+        }
+    }
+}
+
+impl From<EngineError> for BoxesError {
+    fn from(cause: EngineError) -> Self {
+        BoxesError::Engine(cause)
+    }
+}
+
+impl From<SolveError> for BoxesError {
+    fn from(cause: SolveError) -> Self {
+        BoxesError::Solve(cause)
+    }
+}
+
+impl From<SaveParseError> for BoxesError {
+    fn from(cause: SaveParseError) -> Self {
+        BoxesError::SaveParse(cause)
+    }
+}
+
+impl fmt::Display for SaveParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveParseError::Empty => write!(f, "save file is empty"),
+            SaveParseError::MissingTurn => write!(f, "line 1: missing player turn"),
+            SaveParseError::InvalidTurn {
+                line,
+                found,
+                player_count,
+            } => write!(
+                f,
+                "line {line}: player turn '{found}' is not between 1 and {player_count}"
+            ),
+            SaveParseError::MissingRow { line, kind } => {
+                write!(f, "line {line}: expected {kind}, found end of file")
+            }
+            SaveParseError::RowLength {
+                line,
+                kind,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "line {line}: {kind} should have {expected} entries, found {actual}"
+            ),
+            SaveParseError::InvalidEdge {
+                line,
+                column,
+                found,
+            } => write!(
+                f,
+                "line {line}, column {column}: edge '{found}' is not 0 or 1"
+            ),
+            SaveParseError::InvalidCell {
+                line,
+                column,
+                found,
+            } => write!(
+                f,
+                "line {line}, column {column}: cell '{found}' is not a player number"
+            ),
+            SaveParseError::UnknownPlayer {
+                line,
+                column,
+                player,
+            } => write!(
+                f,
+                "line {line}, column {column}: player {player} is greater than the player count"
+            ),
+            SaveParseError::TrailingData { line } => {
+                write!(f, "line {line}: unexpected data after the last cell row")
+            }
+            SaveParseError::InvalidLogEntry { line, found } => write!(
+                f,
+                "line {line}: move '{found}' is not of the form player y x h|v boxes"
+            ),
+            SaveParseError::InconsistentLogEntry { line } => write!(
+                f,
+                "line {line}: move does not match the saved board or turn order"
+            ),
+            SaveParseError::UnsupportedVersion { found } => {
+                write!(f, "line 1: save format version '{found}' is not supported")
+            }
+            SaveParseError::InvalidHeader { line, found } => {
+                write!(f, "line {line}: invalid header '{found}'")
+            }
+            SaveParseError::MissingHeader { field } => write!(
+                f,
+                "save has no {field} header; give height width playercount for older saves"
+            ),
+            SaveParseError::HeaderMismatch { field } => {
+                write!(f, "save {field} does not match the command line")
+            }
+            SaveParseError::MissingSection { line, section } => {
+                write!(f, "line {line}: expected '{section}'")
+            }
+            SaveParseError::InvalidGrid {
+                line,
+                height,
+                width,
+            } => write!(
+                f,
+                "line {line}: grid makes a {height} by {width} board, which is not a valid size"
+            ),
+            SaveParseError::InvalidJson(cause) => write!(f, "{cause}"),
+            SaveParseError::InvalidField { field } => {
+                write!(f, "field '{field}' is missing or invalid")
+            }
+        }
+    }
+}
+
+impl fmt::Display for RowKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RowKind::HorizontalEdges => write!(f, "horizontal edge row"),
+            RowKind::VerticalEdges => write!(f, "vertical edge row"),
+            RowKind::Cells => write!(f, "cell row"),
+        }
+    }
+}
+
+impl Error for SaveParseError {}
@@ -1,16 +1,27 @@
 use std::process;
 
-pub fn handle_error(error: u8) {
+use boxes::error::BoxesError;
+
+pub fn exit_code(error: &BoxesError) -> i32 {
     match error {
-        1 => eprintln!("Usage: boxes height width playercount [filename]"),
-        2 => eprintln!("Invalid grid dimensions"),
-        3 => eprintln!("Invalid player count"),
-        4 => eprintln!("Invalid grid file"),
-        5 => eprintln!("Error reading grid contents"),
-        6 => eprintln!("End of user input"),
-        9 => eprintln!("System call failure"),
-        _ => eprintln!("Unhandled error!"),
+        BoxesError::Usage => 1,
+        BoxesError::Dimensions => 2,
+        BoxesError::PlayerCount => 3,
+        BoxesError::SaveIo(_) => 4,
+        BoxesError::SaveParse(_) => 5,
+        BoxesError::EndOfInput => 6,
+        BoxesError::Solve(_) => 7,
+        BoxesError::Output(_) => 8,
+        BoxesError::System(_) => 9,
+        BoxesError::Engine(_) => 10,
+        BoxesError::Network(_) => 11,
+        BoxesError::Rejected(_) => 12,
+        BoxesError::Player(_) => 13,
     }
+}
+
+pub fn handle_error(error: BoxesError) {
+    eprintln!("{error}");
 
-    process::exit(error.into());
+    process::exit(exit_code(&error));
 }
@@ -1,396 +1,864 @@
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::fs::OpenOptions;
-use std::io::{stdin, stdout, Write};
-
-use crate::configuration;
-struct Game {
-    game_board: Vec<Vec<char>>,
-    player_turn: u8,
-    player_symbols: Vec<char>,
-    player_symbol: char,
-    max_input_y: usize,
-    max_input_x: usize,
-    last_valid_move: (usize, usize, char),
+use std::io::{stdin, stdout, IsTerminal, Write};
+use std::mem;
+
+use crate::analysis::{self, ComponentKind};
+use crate::bot::Bot;
+use crate::configuration::{self, Player, PlayerKind};
+use crate::engine::Engine;
+use crate::error::BoxesError;
+use crate::hint::{self, Hint};
+use crate::rng::Rng;
+use crate::save::{self, SaveFormat};
+use crate::solver;
+use crate::state::{Direction, GameState, Move, MoveRecord};
+use crate::tui;
+use crate::width;
+
+/// ANSI foreground colours for players in order, reused once every one has
+/// been handed out.
+const PLAYER_COLOURS: [u8; 12] = [31, 34, 32, 33, 35, 36, 91, 94, 92, 93, 95, 96];
+
+/// A game in progress with its players, shared by the terminal and network
+/// frontends. Human moves come from the frontend; bots and engines move
+/// through [`Game::computer_turn`].
+pub(crate) struct Game {
+    pub(crate) state: GameState,
+    pub(crate) players: Vec<Player>,
+    save_format: Option<SaveFormat>,
+    cell_width: usize,
+    /// Whether the board is printed in colour.
+    colour: bool,
+    computers: Computers,
+    /// The player who lost by forfeit and why, which ends the game early.
+    pub(crate) forfeit: Option<(u8, String)>,
+}
+
+/// The bots and engines of a game, indexed from player 1. They can be taken
+/// out of the game to think on another thread.
+pub(crate) struct Computers {
+    bots: Vec<Option<Box<dyn Bot>>>,
+    engines: Vec<Option<Engine>>,
 }
-pub fn run(config: configuration::Config) -> Result<String, u8> {
-    let mut game = Game::build(config);
-    game.print();
 
-    let mut game_over: bool = false;
-    let mut change_player;
-    while !game_over {
-        let mut valid_move: bool = false;
-        while !valid_move {
-            print!("{}> ", game.player_symbol);
-            valid_move = game.read_player_move()?;
+impl Computers {
+    /// Chooses a move for the current player without playing it, or gives
+    /// the reason their engine failed. `None` when a human is to move.
+    pub(crate) fn choose_move(&mut self, state: &GameState) -> Option<Result<Move, String>> {
+        let index = (state.current_player() - 1) as usize;
+        if let Some(bot) = self.bots.get_mut(index)?.as_mut() {
+            return Some(Ok(bot.choose_move(state)));
         }
-        println!();
+        let engine = self.engines.get_mut(index)?.as_mut()?;
+
+        Some(engine.choose_move(state).map_err(|cause| cause.to_string()))
+    }
+}
 
-        // Update the game board with player symbol if a box is complete
-        change_player = game.update_game_board();
+pub fn run(config: configuration::Config) -> Result<String, BoxesError> {
+    let tui = config.tui;
+    let mut game = Game::build(config)?;
+    if tui {
+        return tui::run(game);
+    }
+    for (index, engine) in game.computers.engines.iter().enumerate() {
+        if let Some(engine) = engine {
+            println!(
+                "{} is played by {}",
+                game.player_label(index as u8 + 1),
+                engine.name()
+            );
+        }
+    }
+    game.print();
 
-        // Check game over condition
-        game_over = game.check_game_over();
+    while !game.is_finished() {
+        let player = game.state.current_player();
+        if let Some(player_move) = game.computer_turn() {
+            // Echo the computer's move as if it had been typed at the prompt
+            print!("{}> ", game.player_label(player));
+            println!("{player_move}");
+        } else if let Some((player, cause)) = &game.forfeit {
+            println!("{} forfeits: {cause}", game.player_label(*player));
+            break;
+        } else {
+            let mut valid_move: bool = false;
+            while !valid_move {
+                print!("{}> ", game.player_label(player));
+                valid_move = game.read_player_move()?;
+            }
+        }
+        println!();
 
         //Print the game board
         game.print();
+    }
+
+    //Determine winners
+    Ok(game.standings())
+}
+
+/// Steps through the move log of a save from its first move, printing the
+/// board after each one. Enter shows the next move and `q` stops early. Once
+/// stdin is closed the remaining moves are shown without waiting.
+pub fn replay(config: configuration::Config) -> Result<String, BoxesError> {
+    let mut game = Game::build(config)?;
+
+    // Unwind the board to where the log starts
+    let total = game.state.history().len();
+    while game.state.undo().is_some() {}
+    game.print();
 
-        if !game_over && change_player {
-            // Change player turn
-            game.player_turn += 1;
-            if game.player_turn > game.player_symbols.len() as u8 {
-                game.player_turn = 1;
+    let mut waiting = true;
+    let mut step = 0;
+    while game.state.can_redo() {
+        if waiting {
+            let mut input = String::new();
+            let _ = stdout().flush();
+            match stdin().read_line(&mut input) {
+                Ok(0) | Err(_) => waiting = false,
+                Ok(_) if input.trim() == "q" => break,
+                Ok(_) => (),
             }
-            game.player_symbol = game.player_symbols[(game.player_turn - 1) as usize];
         }
+
+        let Some(outcome) = game.state.redo() else {
+            break;
+        };
+        step += 1;
+        let player_move = game.state.history()[game.state.history().len() - 1].player_move;
+        println!(
+            "Move {step}/{total}: {} played {player_move}, completing {} box(es)",
+            game.player_label(outcome.player),
+            outcome.completed.len()
+        );
+        game.print();
     }
 
-    //Determine winners
-    Ok(game.determine_winners())
+    Ok(game.standings())
+}
+
+/// Prints the board of a save and returns the result of perfect play from it:
+/// the best move and the final scores both players reach.
+pub fn solve(config: configuration::Config) -> Result<String, BoxesError> {
+    let game = Game::build(config)?;
+    game.print();
+
+    let solution = solver::solve(&game.state)?;
+    let player = game.state.current_player();
+    let opponent = player % 2 + 1;
+    let scores = game.state.scores();
+    let remaining = (game.state.height() - 1) as i32 * (game.state.width() - 1) as i32
+        - scores.iter().sum::<u32>() as i32;
+    let own = scores[(player - 1) as usize] as i32 + (remaining + solution.margin) / 2;
+    let other = scores[(opponent - 1) as usize] as i32 + (remaining - solution.margin) / 2;
+
+    Ok(format!(
+        "{} to move\nBest move: {}\nMargin: {:+} box(es) from the remaining {remaining}\nFinal score with perfect play: {} {own}, {} {other}\nPositions searched: {}",
+        game.player_label(player),
+        solution.best_move,
+        solution.margin,
+        game.player_label(player),
+        game.player_label(opponent),
+        solution.nodes
+    ))
+}
+
+/// Prints the board of a save and breaks it into chains, loops and isolated
+/// boxes, with who the long chain rule says is heading for control.
+pub fn analyze(config: configuration::Config) -> Result<String, BoxesError> {
+    let game = Game::build(config)?;
+    game.print();
+
+    let analysis = analysis::analyze(&game.state);
+    let mut lines = vec![format!(
+        "{} to move",
+        game.player_label(game.state.current_player())
+    )];
+    lines.push(format!(
+        "Long chains: {}, short chains: {}, loops: {}",
+        analysis.long_chains(),
+        analysis.short_chains(),
+        analysis.loops()
+    ));
+    for component in &analysis.components {
+        let kind = match component.kind {
+            ComponentKind::Chain => format!("Chain of {}", component.len()),
+            ComponentKind::Loop => format!("Loop of {}", component.len()),
+            ComponentKind::Isolated => "Isolated box".to_string(),
+        };
+        let boxes: Vec<String> = component
+            .boxes
+            .iter()
+            .map(|(y, x)| format!("({y}, {x})"))
+            .collect();
+        let capturable = if component.capturable {
+            " (capturable)"
+        } else {
+            ""
+        };
+        lines.push(format!("  {kind}: {}{capturable}", boxes.join(" ")));
+    }
+    lines.push(format!(
+        "Boxes with three or more undrawn sides: {}, safe moves left: {}",
+        analysis.open_boxes.len(),
+        analysis.safe_moves
+    ));
+    match analysis.control {
+        Some(player) => lines.push(format!(
+            "Control: {} by the long chain rule, sacrificing {} box(es) for a controlled value of {:+}",
+            game.player_label(player),
+            analysis.sacrifices,
+            analysis.controlled_value
+        )),
+        None => lines.push("Control: only worked out for two player games in progress".to_string()),
+    }
+
+    Ok(lines.join("\n"))
 }
 
 impl Game {
     fn print(&self) {
-        for row in &self.game_board {
-            for c in row {
-                print!("{c}");
-            }
-            println!();
+        for row in self.add_rulers(self.render_board(self.colour)) {
+            println!("{row}");
         }
     }
 
-    fn build(config: configuration::Config) -> Game {
-        let mut game_board: Vec<Vec<char>> = Vec::new();
+    /// The board as text with its coordinates, one line per row of dots or
+    /// boxes after the column numbers.
+    pub(crate) fn board_lines(&self) -> Vec<String> {
+        self.add_rulers(self.render_board(false))
+    }
 
-        let expanded_height = config.height * 2 - 1;
-        let expanded_width = config.width * 2 - 1;
+    /// The board as text without coordinates, one line per row of dots or
+    /// boxes, so dot `(y, x)` is on line `2y`.
+    pub(crate) fn grid_lines(&self) -> Vec<String> {
+        self.render_board(false)
+    }
 
-        // Generate a default board
-        for row_index in 0..(expanded_height) {
-            let mut game_row: Vec<char> = Vec::new();
-            if row_index % 2 == 0 {
-                for column_index in 0..(expanded_width) {
-                    if column_index % 2 == 0 {
-                        game_row.push('+');
-                    } else {
-                        game_row.push(' ');
-                    }
-                }
-            } else {
-                for _column_index in 0..(expanded_width) {
-                    game_row.push(' ');
-                }
+    /// Numbers the dot rows down the left of a drawn board and the dot
+    /// columns across the top, matching the `y x` of a move. Column numbers
+    /// are written downwards so they line up over their dots however many
+    /// digits they have.
+    fn add_rulers(&self, board: Vec<String>) -> Vec<String> {
+        let gutter = (self.state.height() - 1).to_string().len();
+        let digits = (self.state.width() - 1).to_string().len();
+        let labels: Vec<Vec<char>> = (0..self.state.width())
+            .map(|x| format!("{x:>digits$}").chars().collect())
+            .collect();
+
+        let mut lines = Vec::new();
+        for place in 0..digits {
+            let mut ruler = " ".repeat(gutter + 1);
+            for label in &labels {
+                ruler.push(label[place]);
+                ruler.push_str(&" ".repeat(self.cell_width));
             }
-            game_board.push(game_row);
+            lines.push(ruler.trim_end().to_string());
+        }
+        for (index, line) in board.into_iter().enumerate() {
+            // Only rows of dots have a y of their own
+            let label = if index % 2 == 0 {
+                (index / 2).to_string()
+            } else {
+                String::new()
+            };
+            lines.push(format!("{label:>gutter$} {line}"));
         }
 
-        // Generate maximum input sizes so the calculation doesn't have to always re-evaluate
-        let max_input_y = game_board.len() - ((game_board.len() - 1) / 2) - 1;
-        let max_input_x: usize = game_board[0].len() - ((game_board[0].len() - 1) / 2) - 1;
-
-        // Fill board with data from save if it exists
-        if !config.board_edges.is_empty() && !config.board_cells.is_empty() {
-            // Fill edges
-            let mut x_fill_index: usize;
-            let mut fill_char: char;
-            for (y_fill_index, horizontal) in config.board_edges.iter().enumerate() {
-                if y_fill_index % 2 == 0 {
-                    x_fill_index = 1;
-                    fill_char = '-';
-                } else {
-                    x_fill_index = 0;
-                    fill_char = '|';
-                }
-                for c in horizontal.chars() {
-                    if c == '1' {
-                        game_board[y_fill_index][x_fill_index] = fill_char;
+        lines
+    }
+
+    /// Draws the board. In colour, boxes take their owner's colour, the last
+    /// edge drawn is bold in the colour of whoever drew it and the boxes taken
+    /// on the last turn are shown in reverse.
+    fn render_board(&self, colour: bool) -> Vec<String> {
+        let state = &self.state;
+        let last_turn = if colour { self.last_turn() } else { None };
+        let edge = |text: &str, edge: Move| match &last_turn {
+            Some((last, _)) if last.player_move == edge => {
+                paint(text, &format!("1;{}", player_colour(last.player)))
+            }
+            _ => text.to_string(),
+        };
+
+        let mut lines = Vec::new();
+        // Every cell is as wide as the longest player symbol so columns stay aligned
+        let horizontal_edge = "-".repeat(self.cell_width);
+        let empty_edge = " ".repeat(self.cell_width);
+        for y in 0..state.height() {
+            // Dots and horizontal edges
+            let mut row = String::new();
+            for x in 0..state.width() {
+                row.push('+');
+                if x < state.width() - 1 {
+                    let horizontal = Move::new(y, x, Direction::Horizontal);
+                    if state.has_edge(horizontal) {
+                        row.push_str(&edge(&horizontal_edge, horizontal));
+                    } else {
+                        row.push_str(&empty_edge);
                     }
-                    x_fill_index += 2;
                 }
             }
-
-            // Fill cells
-            let mut y_fill_index: usize = 1;
-            let mut x_fill_index: usize = 1;
-            for horizontal in &config.board_cells {
-                for c in horizontal.chars() {
-                    if c.is_numeric() {
-                        if c != '0' {
-                            game_board[y_fill_index][x_fill_index] =
-                                (c.to_digit(10).unwrap() as u8 + 64) as char;
-                        }
-                        x_fill_index += 2;
+            lines.push(row);
+
+            // Vertical edges and claimed boxes
+            if y < state.height() - 1 {
+                let mut row = String::new();
+                for x in 0..state.width() {
+                    let vertical = Move::new(y, x, Direction::Vertical);
+                    if state.has_edge(vertical) {
+                        row.push_str(&edge("|", vertical));
+                    } else {
+                        row.push(' ');
+                    }
+                    if x < state.width() - 1 {
+                        let cell = match state.owner(y, x) {
+                            0 => " ".repeat(self.cell_width),
+                            owner => {
+                                let symbol = width::pad(self.player_symbol(owner), self.cell_width);
+                                let taken_last_turn = last_turn
+                                    .as_ref()
+                                    .is_some_and(|(_, boxes)| boxes.contains(&(y, x)));
+                                match (colour, taken_last_turn) {
+                                    (false, _) => symbol,
+                                    (true, false) => {
+                                        paint(&symbol, &player_colour(owner).to_string())
+                                    }
+                                    (true, true) => {
+                                        paint(&symbol, &format!("1;7;{}", player_colour(owner)))
+                                    }
+                                }
+                            }
+                        };
+                        row.push_str(&cell);
                     }
                 }
-                y_fill_index += 2;
-                x_fill_index = 1;
+                lines.push(row);
             }
         }
 
+        lines
+    }
+
+    /// The last move played, and the boxes its player completed over their
+    /// whole last turn.
+    fn last_turn(&self) -> Option<(&MoveRecord, Vec<(usize, usize)>)> {
+        let history = self.state.history();
+        let last = history.last()?;
+        // A turn only carries on after a box is completed, so the moves of
+        // one turn are the run of moves by the same player
+        let boxes = history
+            .iter()
+            .rev()
+            .take_while(|record| record.player == last.player)
+            .flat_map(|record| record.completed.iter().copied())
+            .collect();
+
+        Some((last, boxes))
+    }
+
+    pub(crate) fn build(config: configuration::Config) -> Result<Game, BoxesError> {
+        let state = GameState::build(&config);
+
         // Generate player data
-        let player_turn = config.player_turn;
-        let mut player_symbols: Vec<char> = Vec::new();
-        for symbol in 1..config.player_count + 1 {
-            player_symbols.push((symbol + 64) as char);
+        let cell_width = config
+            .players
+            .iter()
+            .map(|player| width::display_width(&player.symbol))
+            .max()
+            .unwrap_or(1);
+
+        let colour = config
+            .colour
+            .resolve(stdout().is_terminal(), env::var_os("NO_COLOR").as_deref());
+
+        // Each bot gets its own generator so a seed replays the same game
+        let mut seeder = config.seed.map_or_else(Rng::from_entropy, Rng::new);
+        let bots = config
+            .players
+            .iter()
+            .map(|player| match player.kind {
+                PlayerKind::Bot(kind) => Some(kind.build(Rng::new(seeder.next_u64()))),
+                _ => None,
+            })
+            .collect();
+        let mut engines = Vec::new();
+        for player in &config.players {
+            engines.push(match &player.kind {
+                PlayerKind::Engine(command) => Some(Engine::start(command, config.move_time)?),
+                _ => None,
+            });
         }
-        let player_symbol = player_symbols[(player_turn - 1) as usize];
-
-        Game {
-            game_board,
-            player_turn,
-            player_symbols,
-            player_symbol,
-            max_input_y,
-            max_input_x,
-            last_valid_move: (0, 0, 'x'),
+
+        Ok(Game {
+            state,
+            players: config.players,
+            save_format: config.save_format,
+            cell_width,
+            colour,
+            computers: Computers { bots, engines },
+            forfeit: None,
+        })
+    }
+
+    /// The width of a box on the board, which is that of the longest symbol.
+    pub(crate) fn cell_width(&self) -> usize {
+        self.cell_width
+    }
+
+    pub(crate) fn player_symbol(&self, player: u8) -> &str {
+        &self.players[(player - 1) as usize].symbol
+    }
+
+    pub(crate) fn player_name(&self, player: u8) -> &str {
+        &self.players[(player - 1) as usize].name
+    }
+
+    /// How a player is shown in messages: their name, followed by their
+    /// symbol when the two differ, such as `Alice (@)`.
+    pub(crate) fn player_label(&self, player: u8) -> String {
+        let (name, symbol) = (self.player_name(player), self.player_symbol(player));
+        if name == symbol {
+            name.to_string()
+        } else {
+            format!("{name} ({symbol})")
         }
     }
 
-    fn read_player_move(&mut self) -> Result<bool, u8> {
+    /// Whether every edge is drawn or a player has forfeited.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.state.is_over() || self.forfeit.is_some()
+    }
+
+    /// Plays the turn of a bot or engine and returns its move, or `None` when
+    /// a human is to move. An engine that fails forfeits instead.
+    pub(crate) fn computer_turn(&mut self) -> Option<Move> {
+        let outcome = self.computers.choose_move(&self.state)?;
+        self.play_computer_move(outcome)
+    }
+
+    /// Plays a move a computer player chose, or forfeits them if they failed
+    /// to choose one.
+    pub(crate) fn play_computer_move(&mut self, outcome: Result<Move, String>) -> Option<Move> {
+        let player = self.state.current_player();
+        match outcome {
+            Ok(player_move) => {
+                self.state
+                    .apply_move(player_move)
+                    .expect("computer players only choose legal moves");
+                Some(player_move)
+            }
+            Err(cause) => {
+                self.forfeit = Some((player, cause));
+                None
+            }
+        }
+    }
+
+    /// Takes the bots and engines out of the game, so it no longer plays any
+    /// turns itself.
+    pub(crate) fn take_computers(&mut self) -> Computers {
+        let empty = Computers {
+            bots: self.players.iter().map(|_| None).collect(),
+            engines: self.players.iter().map(|_| None).collect(),
+        };
+
+        mem::replace(&mut self.computers, empty)
+    }
+
+    fn read_player_move(&mut self) -> Result<bool, BoxesError> {
         let mut player_move: String = String::new();
         let _ = stdout().flush();
 
+        // Zero bytes read means stdin has been closed
         match stdin().read_line(&mut player_move) {
-            Ok(test) => test,
-            Err(_) => return Err(6),
+            Ok(0) | Err(_) => return Err(BoxesError::EndOfInput),
+            Ok(_) => (),
         };
 
         // Trim CR and LF from input and format into vector of strings
         player_move = player_move.replace(['\n', '\r'], "");
-        let player_move: Vec<&str> = player_move.split(' ').collect();
-
-        if player_move.len() == 2 && player_move[0] == "w" {
-            Ok(self.save_game(player_move[1])?)
+        let command: Vec<&str> = player_move.split(' ').collect();
+
+        if command.len() == 2 && command[0] == "w" {
+            self.save_game(command[1])
+        } else if command.len() == 1 && command[0] == "u" {
+            Ok(self.undo_move())
+        } else if command.len() == 1 && command[0] == "r" {
+            Ok(self.redo_move())
+        } else if command.len() == 1 && command[0] == "?" {
+            Ok(self.show_hint())
         } else {
-            Ok(self.validate_player_move(player_move))
+            Ok(self.validate_player_move(&player_move))
         }
     }
 
-    fn validate_player_move(&mut self, player_move: Vec<&str>) -> bool {
-        // Ensure only 3 arguments are given
-        if player_move.len() != 3 {
-            return false;
-        }
-        // Parse input coordinantes as usize. Automatically rejects negative input
-        let Ok(move_y) = player_move[0].parse::<usize>() else {
-            return false;
-        };
-        let Ok(move_x) = player_move[1].parse::<usize>() else {
+    fn validate_player_move(&mut self, player_move: &str) -> bool {
+        let Ok(player_move) = player_move.parse::<Move>() else {
             return false;
         };
 
-        // Check the player edge option
-        if player_move[2].len() != 1 {
-            return false;
-        }
-        let Some(player_edge_option) = player_move[2].chars().next() else {
-            return false;
-        };
+        self.state.apply_move(player_move).is_ok()
+    }
 
-        let translated_y: usize;
-        let translated_x: usize;
-        // Process horizontal moves
-        if player_edge_option == 'h' {
-            if (move_y > self.max_input_y || move_x >= self.max_input_x)
-                || self.game_board[move_y * 2][(move_x * 2) + 1] != ' '
-            {
-                return false;
-            } else {
-                translated_y = move_y * 2;
-                translated_x = (move_x * 2) + 1;
-                self.game_board[translated_y][translated_x] = '-';
+    fn undo_move(&mut self) -> bool {
+        match self.undo() {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("{e}");
+                false
             }
-            // Process vertival moves
-        } else if player_edge_option == 'v' {
-            if (move_y >= self.max_input_y || move_x > self.max_input_x)
-                || self.game_board[(move_y * 2) + 1][move_x * 2] != ' '
-            {
-                return false;
-            } else {
-                translated_y = (move_y * 2) + 1;
-                translated_x = move_x * 2;
-                self.game_board[translated_y][translated_x] = '|';
-            }
-        } else {
-            return false;
         }
-
-        // Save the move for processing later
-        self.last_valid_move = (translated_y, translated_x, player_edge_option);
-
-        true
     }
 
-    fn update_game_board(&mut self) -> bool {
-        let mut change_player = true;
-        let player_symbol = self.player_symbols[(self.player_turn - 1) as usize];
-
-        if self.last_valid_move.2 == 'h' {
-            // Check above
-            if self.last_valid_move.0 > 0 {
-                let above_index_y = self.last_valid_move.0 - 1;
-                if self.game_board[above_index_y - 1][self.last_valid_move.1] == '-'
-                    && self.game_board[above_index_y][self.last_valid_move.1 - 1] == '|'
-                    && self.game_board[above_index_y][self.last_valid_move.1 + 1] == '|'
-                {
-                    self.game_board[above_index_y][self.last_valid_move.1] = player_symbol;
-                    change_player = false;
-                }
-            }
-            // Check below
-            if self.last_valid_move.0 < self.game_board.len() - 1 {
-                let below_index_y = self.last_valid_move.0 + 1;
-                if self.game_board[below_index_y + 1][self.last_valid_move.1] == '-'
-                    && self.game_board[below_index_y][self.last_valid_move.1 - 1] == '|'
-                    && self.game_board[below_index_y][self.last_valid_move.1 + 1] == '|'
-                {
-                    self.game_board[below_index_y][self.last_valid_move.1] = player_symbol;
-                    change_player = false;
-                }
-            }
-        } else if self.last_valid_move.2 == 'v' {
-            // Check left
-            if self.last_valid_move.1 > 0 {
-                let left_index_x = self.last_valid_move.1 - 1;
-                if self.game_board[self.last_valid_move.0][left_index_x - 1] == '|'
-                    && self.game_board[self.last_valid_move.0 - 1][left_index_x] == '-'
-                    && self.game_board[self.last_valid_move.0 + 1][left_index_x] == '-'
-                {
-                    self.game_board[self.last_valid_move.0][left_index_x] = player_symbol;
-                    change_player = false;
-                }
-            }
-            // Check right
-            if self.last_valid_move.1 < self.game_board[0].len() - 1 {
-                let right_index_x = self.last_valid_move.1 + 1;
-                if self.game_board[self.last_valid_move.0][right_index_x + 1] == '|'
-                    && self.game_board[self.last_valid_move.0 - 1][right_index_x] == '-'
-                    && self.game_board[self.last_valid_move.0 + 1][right_index_x] == '-'
-                {
-                    self.game_board[self.last_valid_move.0][right_index_x] = player_symbol;
-                    change_player = false;
-                }
+    fn redo_move(&mut self) -> bool {
+        match self.redo() {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("{e}");
+                false
             }
         }
+    }
 
-        change_player
-    }
-
-    fn check_game_over(&mut self) -> bool {
-        let mut game_over: bool = true;
-        for (y_index, row) in self.game_board.iter().enumerate().skip(1) {
-            // Iterate every second row
-            if y_index % 2 != 0 {
-                for (x_index, c) in row.iter().enumerate().skip(1) {
-                    if x_index % 2 != 0
-                        && (self.game_board[y_index][x_index - 1] != '|'
-                            || self.game_board[y_index][x_index + 1] != '|'
-                            || self.game_board[y_index - 1][x_index] != '-'
-                            || self.game_board[y_index + 1][x_index] != '-')
-                    {
-                        game_over = false;
-                    }
-                }
-            }
+    /// Prints a suggested move. Always returns false as no move was played.
+    fn show_hint(&mut self) -> bool {
+        match self.hint() {
+            Ok(hint) => println!("Hint: {hint}"),
+            Err(e) => eprintln!("{e}"),
         }
 
-        game_over
+        false
     }
 
-    fn determine_winners(self) -> String {
-        // Generate hash map of player scores
-        let mut winners_map: HashMap<char, u16> = HashMap::new();
-        for (row_index, row) in self.game_board.iter().enumerate() {
-            if row_index % 2 != 0 {
-                for (column_index, c) in row.iter().enumerate() {
-                    if column_index % 2 != 0 {
-                        // Insert the key for found player or increment if it exists.
-                        *winners_map.entry(*c).or_insert(0) += 1;
-                    }
-                }
-            }
+    /// Takes back the last move a human made, if the rules allow it. Any bot
+    /// or engine moves after it go too, or the computer would just play
+    /// again.
+    pub(crate) fn undo(&mut self) -> Result<(), &'static str> {
+        if !self.state.rules().allow_undo {
+            return Err("Undo is disabled for this game");
+        }
+        let last_human_move = self
+            .state
+            .history()
+            .iter()
+            .rposition(|record| self.is_human(record.player))
+            .ok_or("Nothing to undo")?;
+        while self.state.history().len() > last_human_move {
+            self.state.undo();
         }
 
-        // Generate string of winners
-        let mut winners = String::new();
-        let mut highest_score = 0;
-        for (key, value) in winners_map.iter() {
-            match value.cmp(&highest_score) {
-                Ordering::Greater => {
-                    highest_score = *value;
-                    winners.clear();
-                    winners.push(*key);
-                }
-                Ordering::Equal => {
-                    winners.push_str(", ");
-                    winners.push(*key);
-                }
-                Ordering::Less => (),
-            };
+        Ok(())
+    }
+
+    /// Plays the last move taken back again, along with the bot and engine
+    /// moves that followed it, if the rules allow it.
+    pub(crate) fn redo(&mut self) -> Result<(), &'static str> {
+        if !self.state.rules().allow_undo {
+            return Err("Undo is disabled for this game");
+        }
+        self.state.redo().ok_or("Nothing to redo")?;
+        while self.state.can_redo()
+            && !self.state.is_over()
+            && !self.is_human(self.state.current_player())
+        {
+            self.state.redo();
         }
 
-        winners
+        Ok(())
     }
 
-    fn save_game(&self, path: &str) -> Result<bool, u8> {
-        let mut edges = String::new();
-        let mut cells: String = String::new();
-        let mut add_comma: bool = false;
+    fn is_human(&self, player: u8) -> bool {
+        self.players[(player - 1) as usize].kind == PlayerKind::Human
+    }
 
-        for (iy, row) in self.game_board.iter().enumerate() {
-            for (ix, c) in row.iter().enumerate() {
-                // Process edge data
-                if ((iy % 2 == 0 && ix % 2 != 0) || (iy % 2 != 0 && ix % 2 == 0)) && *c != ' ' {
-                    edges.push('1');
-                } else if ((iy % 2 == 0 && ix % 2 != 0) || (iy % 2 != 0 && ix % 2 == 0))
-                    && *c == ' '
-                {
-                    edges.push('0');
-                }
+    /// Suggests a move for the player to move and counts it against them.
+    pub(crate) fn hint(&mut self) -> Result<Hint, &'static str> {
+        if !self.state.rules().allow_hints {
+            return Err("Hints are disabled for this game");
+        }
 
-                // Process cell data
-                if iy % 2 != 0 && ix % 2 != 0 && *c == ' ' {
-                    if add_comma {
-                        cells.push(',');
-                    }
-                    add_comma = true;
-                    cells.push('0');
-                } else if iy % 2 != 0 && ix % 2 != 0 && *c != ' ' {
-                    if add_comma {
-                        cells.push(',');
-                    }
-                    add_comma = true;
-                    let Some(char_save_value) = char::from_digit((*c as u32) - 64, 10) else {
-                        return Err(9);
-                    };
-                    cells.push(char_save_value);
+        let hint = hint::hint(&self.state);
+        self.state.record_hint(self.state.current_player());
+
+        Ok(hint)
+    }
+
+    /// The ranked scores and winners of a finished game. A game stopped
+    /// early, such as a replay of a save or a quit, gets the scores so far
+    /// and whose turn it is instead.
+    pub(crate) fn standings(&self) -> String {
+        let scoreboard = self.state.scoreboard();
+        let finished = self.is_finished();
+        let mut standings = String::from(if finished {
+            "Final standings:\n"
+        } else {
+            "Scores so far:\n"
+        });
+        let forfeited = self.forfeit.as_ref().map(|(player, _)| *player);
+        let label_width = (1..=self.state.player_count())
+            .map(|player| width::display_width(&self.player_label(player)))
+            .max()
+            .unwrap_or(1);
+        for standing in scoreboard.standings() {
+            let boxes = if standing.boxes == 1 { "box" } else { "boxes" };
+            let forfeit = match &self.forfeit {
+                Some((player, cause)) if *player == standing.player => {
+                    format!(" (forfeited: {cause})")
                 }
-            }
-            add_comma = false;
+                _ => String::new(),
+            };
+            standings.push_str(&format!(
+                "{:>3}. {}  {} {boxes}{forfeit}\n",
+                standing.rank,
+                width::pad(&self.player_label(standing.player), label_width),
+                standing.boxes,
+            ));
+        }
 
-            edges.push('\n');
-            if iy % 2 != 0 {
-                cells.push('\n');
-            }
+        if finished {
+            // A player who forfeits cannot win, so the best of the rest do
+            let contenders: Vec<_> = scoreboard
+                .standings()
+                .iter()
+                .filter(|standing| Some(standing.player) != forfeited)
+                .collect();
+            let best = contenders.iter().map(|standing| standing.boxes).max();
+            let mut winners: Vec<u8> = contenders
+                .iter()
+                .filter(|standing| Some(standing.boxes) == best)
+                .map(|standing| standing.player)
+                .collect();
+            winners.sort();
+            let winners: Vec<&str> = winners
+                .into_iter()
+                .map(|player| self.player_name(player))
+                .collect();
+            standings.push_str(&format!("Winner(s): {}", winners.join(", ")));
+        } else {
+            let player = self.state.current_player();
+            standings.push_str(&format!("To move: {}", self.player_label(player)));
+        }
+
+        // Only mention hints when someone asked for one
+        let hints = self.state.hints_used();
+        if hints.iter().any(|count| *count > 0) {
+            let counts: Vec<String> = hints
+                .iter()
+                .enumerate()
+                .map(|(index, count)| format!("{} {count}", self.player_name(index as u8 + 1)))
+                .collect();
+            standings.push_str(&format!("\nHints used: {}", counts.join(", ")));
         }
 
+        standings
+    }
+
+    fn save_game(&self, path: &str) -> Result<bool, BoxesError> {
         // Construct single string with newlines to avoid multiple IO
-        let mut save_contents = self.player_turn.to_string();
-        save_contents.push('\n');
-        save_contents.push_str(&edges);
-        save_contents.push_str(&cells);
+        let save_contents = match self.save_format.unwrap_or(SaveFormat::from_path(path)) {
+            SaveFormat::Text => save::write(&self.state, &self.players),
+            SaveFormat::Json => save::write_json(&self.state, &self.players),
+        };
 
         let Ok(_file) = OpenOptions::new().write(true).create_new(true).open(path) else {
             eprintln!("Error opening file for saving grid");
             return Ok(false);
         };
-        let Ok(_result) = fs::write(path, save_contents) else {
-            return Err(9);
-        };
+        fs::write(path, save_contents).map_err(BoxesError::System)?;
         eprintln!("Save of grid successful");
 
         Ok(false)
     }
 }
+
+fn player_colour(player: u8) -> u8 {
+    PLAYER_COLOURS[(player as usize - 1) % PLAYER_COLOURS.len()]
+}
+
+/// Wraps text in an ANSI style, such as `1;31` for bold red.
+fn paint(text: &str, style: &str) -> String {
+    format!("\x1b[{style}m{text}\x1b[0m")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::BotKind;
+    use crate::configuration::ColourMode;
+    use crate::state::Direction::{Horizontal, Vertical};
+
+    fn game(kinds: &[PlayerKind], allow_undo: bool) -> Game {
+        let mut config = configuration::Config::new(3, 3, kinds.len() as u8);
+        for (player, kind) in config.players.iter_mut().zip(kinds) {
+            player.kind = kind.clone();
+        }
+        config.rules.allow_undo = allow_undo;
+        config.colour = ColourMode::Never;
+        config.seed = Some(1);
+
+        Game::build(config).unwrap()
+    }
+
+    #[test]
+    fn undo_takes_back_bot_moves_with_the_human_move() {
+        let mut game = game(&[PlayerKind::Human, PlayerKind::Bot(BotKind::Greedy)], true);
+        game.state.apply_move(Move::new(0, 0, Horizontal)).unwrap();
+        assert!(game.computer_turn().is_some());
+        assert_eq!(game.state.history().len(), 2);
+
+        assert_eq!(game.undo(), Ok(()));
+        assert!(game.state.history().is_empty());
+        assert_eq!(game.state.current_player(), 1);
+
+        // Redo plays the bot's reply again along with the human move
+        assert_eq!(game.redo(), Ok(()));
+        assert_eq!(game.state.history().len(), 2);
+        assert_eq!(game.state.current_player(), 1);
+    }
+
+    #[test]
+    fn undo_with_only_bot_moves_has_nothing_to_take_back() {
+        let mut game = game(&[PlayerKind::Bot(BotKind::Greedy), PlayerKind::Human], true);
+        assert!(game.computer_turn().is_some());
+
+        assert_eq!(game.undo(), Err("Nothing to undo"));
+        assert_eq!(game.state.history().len(), 1);
+    }
+
+    #[test]
+    fn undo_is_refused_when_disabled() {
+        let mut game = game(&[PlayerKind::Human, PlayerKind::Human], false);
+        game.state.apply_move(Move::new(1, 2, Vertical)).unwrap();
+
+        assert_eq!(game.undo(), Err("Undo is disabled for this game"));
+        assert_eq!(game.redo(), Err("Undo is disabled for this game"));
+        assert_eq!(game.state.history().len(), 1);
+    }
+
+    /// A two player game on `height` by `width` dots, with player 2 drawn
+    /// as `symbol`.
+    fn board(height: u16, width: u16, symbol: &str) -> Game {
+        let mut config = configuration::Config::new(height, width, 2);
+        config.players[1].symbol = symbol.to_string();
+        config.colour = ColourMode::Never;
+
+        Game::build(config).unwrap()
+    }
+
+    /// Checks that every digit of a column number sits over its dot.
+    fn assert_labels_over_dots(game: &Game) {
+        let lines = game.board_lines();
+        let digits = (game.state.width() - 1).to_string().len();
+        let dots: Vec<usize> = lines[digits]
+            .char_indices()
+            .filter(|(_, character)| *character == '+')
+            .map(|(column, _)| column)
+            .collect();
+        assert_eq!(dots.len(), game.state.width());
+
+        for (x, column) in dots.into_iter().enumerate() {
+            let label: Vec<char> = format!("{x:>digits$}").chars().collect();
+            for (place, ruler) in lines[..digits].iter().enumerate() {
+                let digit = ruler.chars().nth(column).unwrap_or(' ');
+                assert_eq!(digit, label[place], "column {x} in {lines:#?}");
+            }
+        }
+    }
+
+    #[test]
+    fn column_numbers_sit_over_their_dots() {
+        let game = board(3, 12, "B");
+        let lines = game.board_lines();
+
+        assert_eq!(lines[0], format!("{}1 1", " ".repeat(22)));
+        assert_eq!(lines[1], "  0 1 2 3 4 5 6 7 8 9 0 1");
+        assert_eq!(lines[2], "0 + + + + + + + + + + + +");
+        assert_labels_over_dots(&game);
+    }
+
+    #[test]
+    fn column_numbers_follow_wide_symbols() {
+        let mut game = board(11, 11, "🦀");
+        for (y, x, direction) in [(0, 0, Horizontal), (1, 0, Horizontal), (0, 0, Vertical)] {
+            game.state.apply_move(Move::new(y, x, direction)).unwrap();
+        }
+        game.state.apply_move(Move::new(0, 1, Vertical)).unwrap();
+        let lines = game.board_lines();
+
+        assert_eq!(game.cell_width(), 2);
+        assert_eq!(lines[2], " 0 +--+  +  +  +  +  +  +  +  +  +");
+        assert_eq!(lines[3].trim_end(), "   |🦀|");
+        assert_labels_over_dots(&game);
+    }
+
+    /// Player 1 takes two separate boxes in one turn on a row of three.
+    fn double_capture() -> Game {
+        let mut game = board(2, 4, "B");
+        for (y, x, direction) in [
+            (0, 0, Horizontal),
+            (1, 0, Horizontal),
+            (0, 0, Vertical),
+            (0, 2, Horizontal),
+            (1, 2, Horizontal),
+            (0, 3, Vertical),
+            (0, 1, Vertical),
+            (0, 2, Vertical),
+        ] {
+            game.state.apply_move(Move::new(y, x, direction)).unwrap();
+        }
+
+        game
+    }
+
+    #[test]
+    fn colour_marks_the_last_edge_drawn() {
+        let mut game = board(2, 3, "B");
+        game.state.apply_move(Move::new(0, 0, Horizontal)).unwrap();
+        game.state.apply_move(Move::new(0, 1, Vertical)).unwrap();
+
+        assert_eq!(game.render_board(false), ["+-+ +", "  |  ", "+ + +"]);
+        assert_eq!(
+            game.render_board(true),
+            [
+                "+-+ +".to_string(),
+                format!("  {}  ", paint("|", "1;34")),
+                "+ + +".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn colour_reverses_every_box_of_the_last_turn() {
+        let mut game = double_capture();
+        let taken = paint("A", "1;7;31");
+
+        assert_eq!(game.last_turn().unwrap().1, [(0, 2), (0, 0)]);
+        assert_eq!(game.render_board(false)[1], "|A| |A|");
+        assert_eq!(
+            game.render_board(true)[1],
+            format!("|{taken}| {}{taken}|", paint("|", "1;31"))
+        );
+
+        // A move that takes nothing keeps the turn's boxes marked
+        game.state.apply_move(Move::new(0, 1, Horizontal)).unwrap();
+        assert_eq!(game.last_turn().unwrap().1.len(), 2);
+        assert_eq!(game.render_board(true)[1], format!("|{taken}| |{taken}|"));
+
+        // Once the next player moves, earlier boxes keep only their colour
+        game.state.apply_move(Move::new(1, 1, Horizontal)).unwrap();
+        let owned = paint("A", "31");
+        assert_eq!(game.render_board(false), ["+-+-+-+", "|A|B|A|", "+-+-+-+"]);
+        assert_eq!(
+            game.render_board(true),
+            [
+                "+-+-+-+".to_string(),
+                format!("|{owned}|{}|{owned}|", paint("B", "1;7;34")),
+                format!("+-+{}+-+", paint("-", "1;34")),
+            ]
+        );
+    }
+}
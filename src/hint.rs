@@ -0,0 +1,91 @@
+use std::fmt;
+
+use crate::bot::{double_deals, sacrifice_size, Bot, SearchBot};
+use crate::rng::Rng;
+use crate::solver;
+use crate::state::{GameState, Move};
+
+/// A suggested move and why it was chosen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hint {
+    pub player_move: Move,
+    pub reason: HintReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintReason {
+    /// The move completes this many boxes.
+    Completes(usize),
+    /// The move turns down the last two boxes of a chain so the opponent has
+    /// to open the next one.
+    DoubleDeal,
+    /// The move draws no third side on any box.
+    Safe,
+    /// The move hands this many boxes to the next player.
+    Sacrifice(usize),
+}
+
+impl fmt::Display for HintReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HintReason::Completes(1) => write!(f, "completes a box"),
+            HintReason::Completes(boxes) => write!(f, "completes {boxes} boxes"),
+            HintReason::DoubleDeal => write!(f, "double-dealing keeps control"),
+            HintReason::Safe => write!(f, "safe move"),
+            HintReason::Sacrifice(1) => write!(f, "gives away a box"),
+            HintReason::Sacrifice(boxes) => write!(f, "gives away {boxes} boxes"),
+        }
+    }
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.player_move, self.reason)
+    }
+}
+
+/// Suggests a move for the player to move. Small two player endgames are
+/// solved exactly and anything else is left to the hard bot. Only call this
+/// while the game is not over.
+pub fn hint(state: &GameState) -> Hint {
+    let exact = if state.player_count() == 2 && state.remaining_edges() <= solver::QUICK_SOLVE_EDGES
+    {
+        solver::solve(state).ok().map(|solution| solution.best_move)
+    } else {
+        None
+    };
+    // A fixed seed so asking twice gives the same hint
+    let player_move = exact.unwrap_or_else(|| SearchBot::new(Rng::new(0)).choose_move(state));
+
+    Hint {
+        player_move,
+        reason: reason(state, player_move),
+    }
+}
+
+fn reason(state: &GameState, player_move: Move) -> HintReason {
+    let completed = state.completes(player_move);
+    if completed > 0 {
+        return HintReason::Completes(completed);
+    }
+    if is_double_deal(state, player_move) {
+        return HintReason::DoubleDeal;
+    }
+    if !state.gives_third_side(player_move) {
+        return HintReason::Safe;
+    }
+
+    HintReason::Sacrifice(sacrifice_size(state, player_move))
+}
+
+/// Whether the move is the far edge of a box that the next capture would
+/// otherwise open, leaving both boxes to the opponent.
+fn is_double_deal(state: &GameState, player_move: Move) -> bool {
+    let captures: Vec<Move> = state
+        .legal_moves()
+        .into_iter()
+        .filter(|capture| state.completes(*capture) > 0)
+        .collect();
+
+    double_deals(state, &captures).contains(&player_move)
+}
@@ -0,0 +1,923 @@
+use std::collections::BTreeMap;
+use std::io::{stdout, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::configuration::{valid_board_dimension, valid_player_count, Config, Player};
+use crate::error::BoxesError;
+use crate::json::Json;
+use crate::rng;
+use crate::save::{self, logged_move_to_json, move_from_json, rules_from_json};
+use crate::server::MAX_CREATE_DOTS;
+use crate::state::{GameState, IllegalMove, LoggedMove, Move, MoveOutcome};
+use crate::websocket::{self, Message, MessageReader};
+
+/// The port `boxes http` listens on unless `--port` says otherwise.
+pub const DEFAULT_HTTP_PORT: u16 = 8080;
+
+/// The largest request body accepted, which is plenty for any request the
+/// API takes.
+const MAX_BODY: usize = 64 * 1024;
+
+/// The most bytes the request line and headers may take together, and the
+/// most headers a request may have.
+const MAX_HEADER: usize = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+
+/// The most games still being played that the server holds at once.
+const MAX_LIVE_GAMES: usize = 64;
+
+/// How long a connection may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The settings for `boxes http`, which serves games as JSON resources:
+///
+/// - `POST /games` with `{"height", "width", "players"}` and optionally
+///   `"rules": {"undo", "hints"}` creates a game and returns it. Boards
+///   may have up to 20 dots a side, and once 64 games are being played
+///   more are refused with 429.
+/// - `GET /games` lists every game and `GET /games/{id}` returns one, in the
+///   layout of a JSON save with `id`, `scores`, `over`, `winners` and
+///   `seats_taken` added.
+/// - `POST /games/{id}/seats` with `{"player": n}`, or an empty body for the
+///   first free seat, takes a seat and returns `{"id", "player", "token"}`.
+///   The token is needed to move as that player and is only given out once.
+/// - `POST /games/{id}/moves` with `{"y", "x", "dir": "h"|"v", "token"}`
+///   plays a move for the token's player if it is their turn.
+/// - `GET /games/{id}/moves` returns the moves played so far.
+/// - `GET /games/{id}/events`, upgraded to a WebSocket, pushes the game as it
+///   changes. `?token=...` plays the token's seat over the connection, which
+///   is then announced to the others. Without one the connection watches.
+///
+/// Errors are returned with a 4xx status as
+/// `{"error": {"code": "...", "message": "..."}}`.
+///
+/// Socket messages are JSON objects with a `type`. The server first sends
+/// `state` with the game, then `move_played`, `boxes_completed`,
+/// `turn_changed`, `game_over` with the standings, `player_joined` and
+/// `player_left` as they happen, whether the move came over a socket or
+/// through `POST /games/{id}/moves`. A seated client plays by sending
+/// `{"type": "move", "y", "x", "dir"}`. Moves that fail are answered with
+/// `{"type": "error", "code", "message"}`.
+#[derive(Debug, Clone)]
+pub struct HttpServer {
+    pub port: u16,
+}
+
+impl HttpServer {
+    /// Builds the settings for `boxes http [--port n]`.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<HttpServer, BoxesError> {
+        let mut port = DEFAULT_HTTP_PORT;
+        let mut args = args.skip(2);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--port" => {
+                    let value = args.next().and_then(|value| value.parse::<u16>().ok());
+                    port = value.ok_or(BoxesError::Usage)?;
+                }
+                _ => return Err(BoxesError::Usage),
+            }
+        }
+
+        Ok(HttpServer { port })
+    }
+}
+
+/// The games being served.
+struct Registry {
+    games: BTreeMap<u32, HostedGame>,
+    next_id: u32,
+    next_subscriber: usize,
+}
+
+struct HostedGame {
+    state: GameState,
+    players: Vec<Player>,
+    /// The token given out for each seat, indexed from player 1.
+    tokens: Vec<Option<String>>,
+    subscribers: Vec<Subscriber>,
+}
+
+/// A WebSocket connection receiving a game's events.
+struct Subscriber {
+    id: usize,
+    /// The seat the connection plays, if it took one.
+    player: Option<u8>,
+    writer: Arc<Mutex<TcpStream>>,
+}
+
+/// Events to send to a game's subscribers once the registry is unlocked, so
+/// a slow connection never holds up other requests.
+#[derive(Default)]
+struct Outbox {
+    writers: Vec<Arc<Mutex<TcpStream>>>,
+    events: Vec<Json>,
+}
+
+/// The connection's side of a subscription, held by its own thread.
+struct Socket {
+    game_id: u32,
+    subscriber_id: usize,
+    player: Option<u8>,
+    writer: Arc<Mutex<TcpStream>>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    /// The `Sec-WebSocket-Key` of a request asking to upgrade to a WebSocket.
+    websocket_key: Option<String>,
+    body: String,
+}
+
+/// A failed request, sent as `{"error": {"code", "message"}}`.
+struct ApiError {
+    status: u16,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, code: &'static str, message: impl Into<String>) -> ApiError {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn not_found() -> ApiError {
+        ApiError::new(404, "not_found", "no such resource")
+    }
+
+    fn method_not_allowed() -> ApiError {
+        ApiError::new(
+            405,
+            "method_not_allowed",
+            "method not allowed on this resource",
+        )
+    }
+}
+
+/// Serves requests until the server is stopped, each on its own thread.
+pub fn run(server: HttpServer) -> Result<String, BoxesError> {
+    let listener = TcpListener::bind(("0.0.0.0", server.port)).map_err(BoxesError::Network)?;
+    let port = listener.local_addr().map_err(BoxesError::Network)?.port();
+    println!("Listening on port {port}");
+    stdout().flush().map_err(BoxesError::System)?;
+
+    let registry = Arc::new(Mutex::new(Registry {
+        games: BTreeMap::new(),
+        next_id: 1,
+        next_subscriber: 1,
+    }));
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let registry = Arc::clone(&registry);
+        thread::spawn(move || serve(stream, &registry));
+    }
+
+    Ok(String::new())
+}
+
+/// Answers one request and closes the connection, or keeps it open as a
+/// WebSocket until the client leaves.
+fn serve(mut stream: TcpStream, registry: &Mutex<Registry>) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let request = match read_request(&stream) {
+        Ok(request) => request,
+        Err(e) => return respond(&mut stream, Err(e)),
+    };
+
+    let mut outbox = Outbox::default();
+    let result = match (&request.websocket_key, events_game(&request)) {
+        (Some(key), Some(id)) => match stream.try_clone() {
+            Ok(writer) => {
+                let _ = writer.set_write_timeout(Some(READ_TIMEOUT));
+                let writer = Arc::new(Mutex::new(writer));
+                // Nothing is sent to the connection before the handshake and state
+                let mut handshake = writer.lock().expect("the writer is new");
+                let subscription =
+                    subscribe(&mut lock(registry), &request, id, &writer, &mut outbox);
+                match subscription {
+                    Ok((socket, state)) => {
+                        let response = format!(
+                            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                            websocket::accept_key(key)
+                        );
+                        // A failed write ends the socket's first read, which unsubscribes it
+                        let _ = handshake.write_all(response.as_bytes()).and_then(|_| {
+                            websocket::write_text(&mut *handshake, &state.to_string())
+                        });
+                        drop(handshake);
+                        outbox.send();
+                        return run_socket(stream, registry, socket);
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Err(_) => Err(ApiError::new(
+                400,
+                "bad_request",
+                "could not complete the handshake",
+            )),
+        },
+        _ => handle(&mut lock(registry), &request, &mut outbox),
+    };
+    respond(&mut stream, result);
+    outbox.send();
+}
+
+fn lock(registry: &Mutex<Registry>) -> MutexGuard<'_, Registry> {
+    registry
+        .lock()
+        .expect("no thread panics holding the registry")
+}
+
+fn respond(stream: &mut TcpStream, result: Result<(u16, Json), ApiError>) {
+    let (status, body) = match result {
+        Ok(response) => response,
+        Err(e) => (e.status, error_to_json(&e)),
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        reason_phrase(status),
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+fn error_to_json(e: &ApiError) -> Json {
+    Json::object(vec![(
+        "error",
+        Json::object(vec![
+            ("code", e.code.into()),
+            ("message", e.message.clone().into()),
+        ]),
+    )])
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request, ApiError> {
+    let bad_request = |message: &str| ApiError::new(400, "bad_request", message);
+    let headers_too_large = || {
+        ApiError::new(
+            431,
+            "headers_too_large",
+            format!("requests are limited to {MAX_HEADERS} headers and {MAX_HEADER} bytes before the body"),
+        )
+    };
+    let mut reader = BufReader::new(stream);
+    let mut head = reader.by_ref().take(MAX_HEADER as u64);
+
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)
+        .map_err(|_| bad_request("could not read the request"))?;
+    if head.limit() == 0 {
+        return Err(headers_too_large());
+    }
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(bad_request("malformed request line"));
+    };
+
+    // Only the body length and WebSocket upgrades matter to this API
+    let mut length = 0;
+    let mut upgrade = false;
+    let mut websocket_key = None;
+    let mut headers = 0;
+    loop {
+        let mut header = String::new();
+        head.read_line(&mut header)
+            .map_err(|_| bad_request("could not read the headers"))?;
+        if head.limit() == 0 {
+            return Err(headers_too_large());
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADERS {
+            return Err(headers_too_large());
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .parse::<usize>()
+                    .map_err(|_| bad_request("invalid Content-Length"))?;
+            } else if name.eq_ignore_ascii_case("upgrade") {
+                upgrade = value.eq_ignore_ascii_case("websocket");
+            } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+                websocket_key = Some(value.to_string());
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(ApiError::new(
+            413,
+            "payload_too_large",
+            format!("bodies are limited to {MAX_BODY} bytes"),
+        ));
+    }
+
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| bad_request("the body is shorter than Content-Length"))?;
+    let body = String::from_utf8(body).map_err(|_| bad_request("the body is not UTF-8"))?;
+
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        websocket_key: websocket_key.filter(|_| upgrade),
+        body,
+    })
+}
+
+fn handle(
+    registry: &mut Registry,
+    request: &Request,
+    outbox: &mut Outbox,
+) -> Result<(u16, Json), ApiError> {
+    let segments: Vec<&str> = request.path.split('/').filter(|s| !s.is_empty()).collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["games"]) => create_game(registry, &request.body),
+        ("GET", ["games"]) => {
+            let games = registry
+                .games
+                .iter()
+                .map(|(id, game)| game_to_json(*id, game))
+                .collect();
+            Ok((200, Json::object(vec![("games", Json::Array(games))])))
+        }
+        (_, ["games"]) => Err(ApiError::method_not_allowed()),
+        (method, ["games", id, rest @ ..]) => {
+            let id = id.parse::<u32>().map_err(|_| ApiError::not_found())?;
+            let game = registry
+                .games
+                .get_mut(&id)
+                .ok_or_else(ApiError::not_found)?;
+            match (method, rest) {
+                ("GET", []) => Ok((200, game_to_json(id, game))),
+                ("GET", ["moves"]) => {
+                    let moves = game
+                        .state
+                        .history()
+                        .iter()
+                        .map(|record| logged_move_to_json(&LoggedMove::from(record)))
+                        .collect();
+                    Ok((
+                        200,
+                        Json::object(vec![("id", id.into()), ("moves", Json::Array(moves))]),
+                    ))
+                }
+                ("POST", ["moves"]) => play_move(game, &request.body, outbox),
+                ("POST", ["seats"]) => take_seat(id, game, &request.body),
+                ("GET", ["events"]) => Err(ApiError::new(
+                    426,
+                    "upgrade_required",
+                    "events are sent over a WebSocket",
+                )),
+                (_, [] | ["moves"] | ["seats"] | ["events"]) => Err(ApiError::method_not_allowed()),
+                _ => Err(ApiError::not_found()),
+            }
+        }
+        _ => Err(ApiError::not_found()),
+    }
+}
+
+fn create_game(registry: &mut Registry, body: &str) -> Result<(u16, Json), ApiError> {
+    let document = parse_body(body)?;
+    let number = |field: &str| {
+        document.get(field).and_then(Json::as_u64).ok_or_else(|| {
+            ApiError::new(
+                400,
+                "invalid_field",
+                format!("{field} must be a whole number"),
+            )
+        })
+    };
+    let height = number("height")?.min(u16::MAX as u64) as u16;
+    let width = number("width")?.min(u16::MAX as u64) as u16;
+    let player_count = number("players")?.min(u8::MAX as u64) as u8;
+    let valid_dimension = |dots| valid_board_dimension(dots) && dots <= MAX_CREATE_DOTS;
+    if !valid_dimension(height) || !valid_dimension(width) {
+        return Err(ApiError::new(
+            422,
+            "invalid_dimensions",
+            format!("height and width must be from 2 to {MAX_CREATE_DOTS} dots"),
+        ));
+    }
+    if !valid_player_count(player_count) {
+        return Err(ApiError::new(
+            422,
+            "invalid_player_count",
+            "players must be from 2 to 100",
+        ));
+    }
+
+    let live = registry
+        .games
+        .values()
+        .filter(|game| !game.state.is_over())
+        .count();
+    if live >= MAX_LIVE_GAMES {
+        return Err(ApiError::new(
+            429,
+            "too_many_games",
+            format!("the server already has {MAX_LIVE_GAMES} games being played"),
+        ));
+    }
+
+    let mut config = Config::new(height, width, player_count);
+    if let Some(rules) = document.get("rules") {
+        config.rules = rules_from_json(rules).ok_or_else(|| {
+            ApiError::new(
+                400,
+                "invalid_field",
+                "rules may only set undo and hints to true or false",
+            )
+        })?;
+    }
+    let game = HostedGame {
+        state: GameState::build(&config),
+        tokens: vec![None; config.players.len()],
+        players: config.players,
+        subscribers: Vec::new(),
+    };
+
+    let id = registry.next_id;
+    registry.next_id += 1;
+    let json = game_to_json(id, &game);
+    registry.games.insert(id, game);
+
+    Ok((201, json))
+}
+
+/// Gives out the token for a seat nobody has taken.
+fn take_seat(id: u32, game: &mut HostedGame, body: &str) -> Result<(u16, Json), ApiError> {
+    let requested = if body.trim().is_empty() {
+        None
+    } else {
+        let document = parse_body(body)?;
+        match document.get("player") {
+            Some(player) => Some(player.as_u64().ok_or_else(|| {
+                ApiError::new(400, "invalid_field", "player must be a whole number")
+            })?),
+            None => None,
+        }
+    };
+
+    let seat = match requested {
+        Some(player) => {
+            let seat = (player as usize)
+                .checked_sub(1)
+                .filter(|seat| *seat < game.tokens.len())
+                .ok_or_else(|| {
+                    ApiError::new(400, "invalid_field", "player must be a seat in the game")
+                })?;
+            if game.tokens[seat].is_some() {
+                return Err(ApiError::new(
+                    409,
+                    "seat_taken",
+                    "this seat has already been taken",
+                ));
+            }
+            seat
+        }
+        None => game
+            .tokens
+            .iter()
+            .position(Option::is_none)
+            .ok_or_else(|| ApiError::new(409, "no_free_seat", "every seat has been taken"))?,
+    };
+
+    let token = rng::token();
+    game.tokens[seat] = Some(token.clone());
+    Ok((
+        201,
+        Json::object(vec![
+            ("id", id.into()),
+            ("player", (seat + 1).into()),
+            ("token", token.into()),
+        ]),
+    ))
+}
+
+/// Plays a move with the same validation as the terminal game, for the
+/// player whose token came with it.
+fn play_move(
+    game: &mut HostedGame,
+    body: &str,
+    outbox: &mut Outbox,
+) -> Result<(u16, Json), ApiError> {
+    let document = parse_body(body)?;
+    let player_move = parse_move(&document)?;
+    let token = document
+        .get("token")
+        .and_then(Json::as_str)
+        .ok_or_else(|| {
+            ApiError::new(
+                401,
+                "missing_token",
+                "moves need the token of the player making them",
+            )
+        })?;
+    let player = game.player_with_token(token)?;
+    let outcome = game.play(player, player_move, outbox)?;
+
+    let record = &game.state.history()[game.state.history().len() - 1];
+    let Json::Object(mut fields) = logged_move_to_json(&LoggedMove::from(record)) else {
+        unreachable!("moves are written as objects");
+    };
+    fields.push(("completed".to_string(), boxes_to_json(&outcome.completed)));
+    fields.push(("next_player".to_string(), outcome.next_player.into()));
+    fields.push(("game_over".to_string(), outcome.game_over.into()));
+
+    Ok((201, Json::Object(fields)))
+}
+
+/// Reads `{"y", "x", "dir"}`.
+fn parse_move(document: &Json) -> Result<Move, ApiError> {
+    move_from_json(document).ok_or_else(|| {
+        ApiError::new(
+            400,
+            "invalid_move",
+            "moves are written as {\"y\": n, \"x\": n, \"dir\": \"h\"|\"v\"}",
+        )
+    })
+}
+
+impl HostedGame {
+    /// The player a seat token was given to.
+    fn player_with_token(&self, token: &str) -> Result<u8, ApiError> {
+        let seat = self
+            .tokens
+            .iter()
+            .position(|held| held.as_deref() == Some(token))
+            .ok_or_else(|| {
+                ApiError::new(
+                    403,
+                    "invalid_token",
+                    "the token is not for a seat in this game",
+                )
+            })?;
+
+        Ok(seat as u8 + 1)
+    }
+
+    /// Plays a move for `player` and queues up events telling every
+    /// subscriber what happened.
+    fn play(
+        &mut self,
+        player: u8,
+        player_move: Move,
+        outbox: &mut Outbox,
+    ) -> Result<MoveOutcome, ApiError> {
+        if !self.state.is_over() && player != self.state.current_player() {
+            return Err(ApiError::new(
+                409,
+                "not_your_turn",
+                "it is not this player's turn",
+            ));
+        }
+
+        let outcome = self.state.apply_move(player_move).map_err(|e| {
+            let (status, code) = match e {
+                IllegalMove::Malformed => (400, "invalid_move"),
+                IllegalMove::OutOfBounds => (422, "out_of_bounds"),
+                IllegalMove::AlreadyDrawn => (409, "already_drawn"),
+                IllegalMove::GameOver => (409, "game_over"),
+            };
+            ApiError::new(status, code, e.to_string())
+        })?;
+        for event in self.move_events(&outcome) {
+            self.publish(event, outbox);
+        }
+
+        Ok(outcome)
+    }
+
+    fn move_events(&self, outcome: &MoveOutcome) -> Vec<Json> {
+        let record = &self.state.history()[self.state.history().len() - 1];
+        let Json::Object(fields) = logged_move_to_json(&LoggedMove::from(record)) else {
+            unreachable!("moves are written as objects");
+        };
+        let mut events = vec![event("move_played", fields)];
+
+        if !outcome.completed.is_empty() {
+            events.push(event(
+                "boxes_completed",
+                vec![
+                    ("player".to_string(), outcome.player.into()),
+                    ("boxes".to_string(), boxes_to_json(&outcome.completed)),
+                    ("scores".to_string(), self.state.scores().into()),
+                ],
+            ));
+        }
+        if outcome.game_over {
+            let standings = self
+                .state
+                .scoreboard()
+                .standings()
+                .iter()
+                .map(|standing| {
+                    Json::object(vec![
+                        ("player", standing.player.into()),
+                        (
+                            "name",
+                            self.players[standing.player as usize - 1]
+                                .name
+                                .clone()
+                                .into(),
+                        ),
+                        ("boxes", standing.boxes.into()),
+                        ("rank", standing.rank.into()),
+                    ])
+                })
+                .collect();
+            events.push(event(
+                "game_over",
+                vec![
+                    ("scores".to_string(), self.state.scores().into()),
+                    ("standings".to_string(), Json::Array(standings)),
+                    ("winners".to_string(), self.state.winners().into()),
+                ],
+            ));
+        } else if outcome.next_player != outcome.player {
+            events.push(event(
+                "turn_changed",
+                vec![("player".to_string(), outcome.next_player.into())],
+            ));
+        }
+
+        events
+    }
+
+    /// Queues an event for every subscriber as they are now.
+    fn publish(&self, event: Json, outbox: &mut Outbox) {
+        if outbox.writers.is_empty() {
+            outbox.writers = self
+                .subscribers
+                .iter()
+                .map(|subscriber| Arc::clone(&subscriber.writer))
+                .collect();
+        }
+        outbox.events.push(event);
+    }
+}
+
+impl Outbox {
+    /// Sends the events. A connection that cannot keep up is shut down, and
+    /// its own thread then removes it. Call this without the registry locked.
+    fn send(self) {
+        let texts: Vec<String> = self.events.iter().map(Json::to_string).collect();
+        for writer in &self.writers {
+            let mut writer = writer.lock().expect("no thread panics holding a writer");
+            for text in &texts {
+                if websocket::write_text(&mut *writer, text).is_err() {
+                    let _ = writer.shutdown(Shutdown::Both);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// An event of the given type, with `fields` following the type.
+fn event(kind: &str, fields: Vec<(String, Json)>) -> Json {
+    let mut event = vec![("type".to_string(), kind.into())];
+    event.extend(fields);
+
+    Json::Object(event)
+}
+
+fn boxes_to_json(boxes: &[(usize, usize)]) -> Json {
+    Json::Array(
+        boxes
+            .iter()
+            .map(|(y, x)| Json::object(vec![("y", (*y).into()), ("x", (*x).into())]))
+            .collect(),
+    )
+}
+
+/// The game whose events a request asks for, if it is `GET /games/{id}/events`.
+fn events_game(request: &Request) -> Option<u32> {
+    let segments: Vec<&str> = request.path.split('/').filter(|s| !s.is_empty()).collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["games", id, "events"]) => id.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Subscribes a connection to a game, as the player whose token it gave if
+/// it gave one, and returns the `state` event it should be sent first.
+fn subscribe(
+    registry: &mut Registry,
+    request: &Request,
+    id: u32,
+    writer: &Arc<Mutex<TcpStream>>,
+    outbox: &mut Outbox,
+) -> Result<(Socket, Json), ApiError> {
+    let subscriber_id = registry.next_subscriber;
+    let game = registry
+        .games
+        .get_mut(&id)
+        .ok_or_else(ApiError::not_found)?;
+
+    let mut player = None;
+    for parameter in request.query.split('&').filter(|p| !p.is_empty()) {
+        match parameter.split_once('=') {
+            Some(("token", token)) => player = Some(game.player_with_token(token)?),
+            _ => {
+                return Err(ApiError::new(
+                    400,
+                    "invalid_field",
+                    "unknown query parameter",
+                ))
+            }
+        }
+    }
+    if player.is_some() && game.subscribers.iter().any(|s| s.player == player) {
+        return Err(ApiError::new(
+            409,
+            "seat_taken",
+            "another connection plays this seat",
+        ));
+    }
+
+    let state = event("state", vec![("game".to_string(), game_to_json(id, game))]);
+    registry.next_subscriber += 1;
+    game.subscribers.push(Subscriber {
+        id: subscriber_id,
+        player,
+        writer: Arc::clone(writer),
+    });
+    if let Some(player) = player {
+        game.publish(
+            event("player_joined", vec![("player".to_string(), player.into())]),
+            outbox,
+        );
+    }
+
+    Ok((
+        Socket {
+            game_id: id,
+            subscriber_id,
+            player,
+            writer: Arc::clone(writer),
+        },
+        state,
+    ))
+}
+
+/// Reads moves from a subscribed connection until it closes, then removes
+/// it from its game.
+fn run_socket(stream: TcpStream, registry: &Mutex<Registry>, socket: Socket) {
+    let _ = stream.set_read_timeout(None);
+    let mut reader = MessageReader::new(BufReader::new(&stream));
+    loop {
+        let result = match reader.read_message() {
+            Ok(Message::Text(text)) => {
+                let mut outbox = Outbox::default();
+                let result = socket_move(&mut lock(registry), &socket, &text, &mut outbox);
+                outbox.send();
+                result
+            }
+            Ok(Message::Binary(_)) => Err(ApiError::new(
+                400,
+                "invalid_json",
+                "messages must be JSON text",
+            )),
+            Ok(Message::Ping(data)) => {
+                let mut writer = socket
+                    .writer
+                    .lock()
+                    .expect("no thread panics holding a writer");
+                let _ = websocket::write_pong(&mut *writer, &data);
+                Ok(())
+            }
+            Ok(Message::Close) | Err(_) => break,
+        };
+        if let Err(e) = result {
+            let error = event(
+                "error",
+                vec![
+                    ("code".to_string(), e.code.into()),
+                    ("message".to_string(), e.message.into()),
+                ],
+            );
+            let mut writer = socket
+                .writer
+                .lock()
+                .expect("no thread panics holding a writer");
+            let _ = websocket::write_text(&mut *writer, &error.to_string());
+        }
+    }
+
+    {
+        let mut writer = socket
+            .writer
+            .lock()
+            .expect("no thread panics holding a writer");
+        let _ = websocket::write_close(&mut *writer);
+    }
+    let mut outbox = Outbox::default();
+    if let Some(game) = lock(registry).games.get_mut(&socket.game_id) {
+        game.subscribers.retain(|s| s.id != socket.subscriber_id);
+        if let Some(player) = socket.player {
+            game.publish(
+                event("player_left", vec![("player".to_string(), player.into())]),
+                &mut outbox,
+            );
+        }
+    }
+    outbox.send();
+}
+
+/// Plays a `{"type": "move", ...}` message as the connection's seat.
+fn socket_move(
+    registry: &mut Registry,
+    socket: &Socket,
+    text: &str,
+    outbox: &mut Outbox,
+) -> Result<(), ApiError> {
+    let document = parse_body(text)?;
+    if document.get("type").and_then(Json::as_str) != Some("move") {
+        return Err(ApiError::new(
+            400,
+            "invalid_field",
+            "messages must have the type move",
+        ));
+    }
+    let player_move = parse_move(&document)?;
+    let player = socket.player.ok_or_else(|| {
+        ApiError::new(
+            401,
+            "missing_token",
+            "connect with the token of a seat to move",
+        )
+    })?;
+    let game = registry
+        .games
+        .get_mut(&socket.game_id)
+        .ok_or_else(ApiError::not_found)?;
+    game.play(player, player_move, outbox)?;
+
+    Ok(())
+}
+
+fn parse_body(body: &str) -> Result<Json, ApiError> {
+    Json::parse(body).map_err(|e| ApiError::new(400, "invalid_json", e.to_string()))
+}
+
+/// A game as a JSON save with its id, the scores, whether it is over and,
+/// once it is, the winners.
+fn game_to_json(id: u32, game: &HostedGame) -> Json {
+    let Json::Object(mut fields) = save::to_json(&game.state, &game.players) else {
+        unreachable!("saves are written as objects");
+    };
+    fields.insert(0, ("id".to_string(), id.into()));
+    fields.push(("scores".to_string(), game.state.scores().into()));
+    fields.push(("over".to_string(), game.state.is_over().into()));
+    let winners = if game.state.is_over() {
+        game.state.winners()
+    } else {
+        Vec::new()
+    };
+    fields.push(("winners".to_string(), winners.into()));
+    let seats_taken: Vec<usize> = (1..=game.tokens.len())
+        .filter(|player| game.tokens[player - 1].is_some())
+        .collect();
+    fields.push(("seats_taken".to_string(), seats_taken.into()));
+
+    Json::Object(fields)
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        426 => "Upgrade Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        _ => "Error",
+    }
+}
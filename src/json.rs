@@ -0,0 +1,540 @@
+use std::error::Error;
+use std::fmt;
+
+/// How deeply arrays and objects may nest. The parser recurses once per
+/// level, so without a limit a long run of `[` overflows the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON value. Objects keep their keys in the order they were read
+/// or inserted so written files are stable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Why a JSON document could not be parsed. Lines and columns start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    pub line: usize,
+    pub column: usize,
+    pub message: &'static str,
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            position: 0,
+            depth: 0,
+        };
+        parser.skip_whitespace();
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.position < parser.chars.len() {
+            return Err(parser.error("unexpected data after the document"));
+        }
+
+        Ok(value)
+    }
+
+    /// Builds an object from `(key, value)` pairs.
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Looks up a key if this is an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The value as a whole number, if it is one.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(value) if *value >= 0.0 && value.fract() == 0.0 => Some(*value as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+macro_rules! json_from_number {
+    ($($number:ty),*) => {
+        $(impl From<$number> for Json {
+            fn from(value: $number) -> Self {
+                Json::Number(value as f64)
+            }
+        })*
+    };
+}
+
+json_from_number!(u8, u16, u32, u64, usize, i32, i64, f64);
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Self {
+        Json::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{value}"),
+            Json::Number(value) if value.is_finite() => write!(f, "{value}"),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(value) => write_string(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for JsonError {}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// The arrays and objects open around the current position.
+    depth: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &'static str) -> JsonError {
+        let before = &self.chars[..self.position.min(self.chars.len())];
+        let line = before.iter().filter(|c| **c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|c| **c != '\n').count() + 1;
+
+        JsonError {
+            line,
+            column,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char, message: &'static str) -> Result<(), JsonError> {
+        if self.peek() != Some(expected) {
+            return Err(self.error(message));
+        }
+        self.position += 1;
+
+        Ok(())
+    }
+
+    fn parse_value(&mut self) -> Result<Json, JsonError> {
+        match self.peek() {
+            Some('{') => self.nested(Parser::parse_object),
+            Some('[') => self.nested(Parser::parse_array),
+            Some('"') => Ok(Json::String(self.parse_string()?)),
+            Some('t') => self.parse_literal("true", Json::Bool(true)),
+            Some('f') => self.parse_literal("false", Json::Bool(false)),
+            Some('n') => self.parse_literal("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of document")),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Parser) -> Result<Json, JsonError>,
+    ) -> Result<Json, JsonError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+
+        value
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, JsonError> {
+        for expected in literal.chars() {
+            self.expect(expected, "invalid literal")?;
+        }
+
+        Ok(value)
+    }
+
+    /// Reads a number, which JSON writes as an optional minus sign, then a
+    /// zero or digits without a leading zero, then an optional fraction and
+    /// exponent.
+    fn parse_number(&mut self) -> Result<Json, JsonError> {
+        let start = self.position;
+        let valid = self.skip_number();
+        let text: String = self.chars[start..self.position].iter().collect();
+        match text.parse::<f64>() {
+            Ok(value) if valid => Ok(Json::Number(value)),
+            _ => {
+                self.position = start;
+                Err(self.error("invalid number"))
+            }
+        }
+    }
+
+    /// Moves past a number and says whether it had the right form.
+    fn skip_number(&mut self) -> bool {
+        if self.peek() == Some('-') {
+            self.position += 1;
+        }
+        match self.peek() {
+            Some('0') => {
+                self.position += 1;
+                if self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    return false;
+                }
+            }
+            Some(c) if c.is_ascii_digit() => {
+                self.skip_digits();
+            }
+            _ => return false,
+        }
+        if self.peek() == Some('.') {
+            self.position += 1;
+            if !self.skip_digits() {
+                return false;
+            }
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.position += 1;
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.position += 1;
+            }
+            if !self.skip_digits() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Moves past a run of digits and says whether there were any.
+    fn skip_digits(&mut self) -> bool {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+
+        self.position > start
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.expect('"', "expected a string")?;
+        let mut value = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.position += 1;
+            match c {
+                '"' => return Ok(value),
+                '\\' => {
+                    let Some(escape) = self.peek() else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.position += 1;
+                    match escape {
+                        '"' => value.push('"'),
+                        '\\' => value.push('\\'),
+                        '/' => value.push('/'),
+                        'b' => value.push('\u{8}'),
+                        'f' => value.push('\u{c}'),
+                        'n' => value.push('\n'),
+                        'r' => value.push('\r'),
+                        't' => value.push('\t'),
+                        'u' => value.push(self.parse_unicode_escape()?),
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.parse_hex()?;
+        // Characters outside the basic plane are written as a surrogate pair
+        let code = if (0xD800..0xDC00).contains(&high) {
+            self.expect('\\', "expected a low surrogate")?;
+            self.expect('u', "expected a low surrogate")?;
+            let low = self.parse_hex()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("invalid low surrogate"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, JsonError> {
+        let mut value = 0;
+        for offset in 0..4 {
+            // from_str_radix would also take a leading sign, so go digit by digit
+            let Some(digit) = self
+                .chars
+                .get(self.position + offset)
+                .and_then(|c| c.to_digit(16))
+            else {
+                return Err(self.error("invalid unicode escape"));
+            };
+            value = value * 16 + digit;
+        }
+        self.position += 4;
+
+        Ok(value)
+    }
+
+    fn parse_array(&mut self) -> Result<Json, JsonError> {
+        self.expect('[', "expected an array")?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            self.skip_whitespace();
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, JsonError> {
+        self.expect('{', "expected an object")?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':', "expected ':'")?;
+            self.skip_whitespace();
+            fields.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values() {
+        let value = Json::parse(r#" {"a": [1, -2.5e3, true, null], "b": {"c": "d"}} "#).unwrap();
+
+        assert_eq!(
+            value.get("a"),
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::Number(-2500.0),
+                Json::Bool(true),
+                Json::Null,
+            ]))
+        );
+        assert_eq!(value.get("b").and_then(|b| b.get("c")), Some(&"d".into()));
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        let value = Json::object(vec![
+            (
+                "text",
+                "quote \" slash \\ tab \t bell \u{7} snow \u{2603}".into(),
+            ),
+            ("numbers", vec![0u64, 7, 1_000_000].into()),
+            ("empty", Json::Object(Vec::new())),
+        ]);
+
+        assert_eq!(Json::parse(&value.to_string()), Ok(value));
+    }
+
+    #[test]
+    fn decodes_unicode_escapes_and_surrogate_pairs() {
+        assert_eq!(
+            Json::parse(r#""A\u00e9\ud83d\ude00""#),
+            Ok("A\u{e9}\u{1f600}".into())
+        );
+        assert!(Json::parse(r#""\ud83d""#).is_err());
+    }
+
+    #[test]
+    fn rejects_signs_in_unicode_escapes() {
+        for text in [r#""\u+041""#, r#""\u-041""#, r#""\u 041""#, r#""\u04""#] {
+            let error = Json::parse(text).unwrap_err();
+            assert_eq!(error.message, "invalid unicode escape", "{text}");
+        }
+    }
+
+    #[test]
+    fn accepts_only_json_numbers() {
+        for text in ["0", "-0", "10", "0.5", "-12.25", "1e3", "1E-3", "2.5e+2"] {
+            assert!(Json::parse(text).is_ok(), "{text}");
+        }
+        for text in [
+            "012", "-01", "00", "1.", ".5", "-", "1e", "1e+", "+1", "1-2", "0x1",
+        ] {
+            assert!(Json::parse(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+
+        let error = Json::parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(error.message, "nesting too deep");
+        assert_eq!(error.column, MAX_DEPTH + 1);
+
+        // Deep enough to overflow the stack without the limit
+        let error = Json::parse(&"[{\"a\":".repeat(60_000)).unwrap_err();
+        assert_eq!(error.message, "nesting too deep");
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        let error = Json::parse("{\n  \"a\": 1,\n  \"b\" 2\n}").unwrap_err();
+
+        assert_eq!(
+            error,
+            JsonError {
+                line: 3,
+                column: 7,
+                message: "expected ':'",
+            }
+        );
+    }
+}
@@ -1,3 +1,21 @@
+pub mod analysis;
+pub mod bot;
+pub mod client;
 pub mod configuration;
-pub mod error_handler;
+pub mod engine;
+pub mod error;
 pub mod game;
+pub mod hint;
+pub mod http;
+pub mod json;
+pub mod mcts;
+pub mod rng;
+pub mod save;
+pub mod scoreboard;
+pub mod server;
+pub mod solver;
+pub mod state;
+pub mod tournament;
+pub mod tui;
+pub mod websocket;
+pub mod width;
@@ -1,18 +1,34 @@
 use std::env;
 
-use boxes::configuration;
-use boxes::error_handler::handle_error;
-use boxes::game::run;
-fn main() {
-    let config = match configuration::Config::build(env::args()) {
-        Ok(config) => config,
-        Err(e) => return handle_error(e),
-    };
+use boxes::client::{self, Client};
+use boxes::configuration::Config;
+use boxes::engine::{self, EngineMode};
+use boxes::game::{analyze, replay, run, solve};
+use boxes::http::{self, HttpServer};
+use boxes::server::{self, Server};
+use boxes::tournament::{self, Tournament};
 
-    let winners: String = match run(config) {
-        Ok(winners) => winners,
-        Err(e) => return handle_error(e),
+mod error_handler;
+use error_handler::handle_error;
+
+fn main() {
+    // Commands that work on a save name it as their only argument
+    let result = match env::args().nth(1).as_deref() {
+        Some("replay") => Config::build_replay(env::args()).and_then(replay),
+        Some("solve") => Config::build_replay(env::args()).and_then(solve),
+        Some("analyze") => Config::build_replay(env::args()).and_then(analyze),
+        Some("serve") => Server::build(env::args()).and_then(server::run),
+        Some("connect") => Client::build(env::args()).and_then(client::run),
+        Some("http") => HttpServer::build(env::args()).and_then(http::run),
+        Some("engine") => EngineMode::build(env::args()).and_then(engine::run),
+        Some("tournament") => Tournament::build(env::args()).and_then(tournament::run),
+        _ => Config::build(env::args()).and_then(run),
     };
 
-    println!("Winner(s): {winners}");
+    // Servers and the engine print as they go and have nothing left to say
+    match result {
+        Ok(output) if output.is_empty() => (),
+        Ok(output) => println!("{output}"),
+        Err(e) => handle_error(e),
+    }
 }
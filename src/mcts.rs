@@ -0,0 +1,478 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::bot::{double_deals, sacrifice_size, Bot};
+use crate::rng::Rng;
+use crate::solver;
+use crate::state::{Direction, GameState, Move};
+
+/// How long the MCTS bot thinks about each move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    /// A fixed number of playouts, so games repeat exactly from a seed.
+    Iterations(u32),
+    /// As many playouts as fit in the time.
+    Time(Duration),
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget::Iterations(1000)
+    }
+}
+
+/// Written as a number of iterations such as `5000`, or a time such as
+/// `500ms` or `2s`.
+impl FromStr for Budget {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let budget = if let Some(millis) = s.strip_suffix("ms") {
+            Budget::Time(Duration::from_millis(millis.parse().map_err(|_| ())?))
+        } else if let Some(seconds) = s.strip_suffix('s') {
+            Budget::Time(Duration::from_secs(seconds.parse().map_err(|_| ())?))
+        } else {
+            Budget::Iterations(s.parse().map_err(|_| ())?)
+        };
+
+        match budget {
+            Budget::Iterations(0) => Err(()),
+            Budget::Time(time) if time.is_zero() => Err(()),
+            _ => Ok(budget),
+        }
+    }
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Budget::Iterations(iterations) => write!(f, "{iterations}"),
+            Budget::Time(time) => write!(f, "{}ms", time.as_millis()),
+        }
+    }
+}
+
+/// The UCT exploration constant.
+const EXPLORATION: f64 = 1.4;
+/// The most moves a tree node considers. Only captures can exceed it.
+const WIDTH: usize = 12;
+/// Random edges drawn when looking for moves on a board too big to scan.
+const SAMPLES: usize = 64;
+/// Boards with at most this many edges left are scanned in full.
+const FULL_SCAN: usize = 256;
+/// Sacrifices compared when a playout has to give boxes away.
+const SACRIFICE_TRIES: usize = 4;
+/// Playouts stop after this many moves so huge boards stay responsive.
+const PLAYOUT_DEPTH: usize = 500;
+
+struct Node {
+    player_move: Option<Move>,
+    /// The player who made `player_move`, or 0 at the root.
+    player: u8,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    visits: u32,
+    /// The total reward for `player` over every playout through this node.
+    reward: f64,
+}
+
+/// Monte Carlo tree search with UCT selection. Playouts take boxes greedily
+/// and avoid drawing third sides, and small two player endgames are left to
+/// the exact solver. Only a handful of moves are tried at each node, so the
+/// search scales to the largest boards.
+pub struct MctsBot {
+    rng: Rng,
+    budget: Budget,
+}
+
+impl MctsBot {
+    pub fn new(rng: Rng, budget: Budget) -> MctsBot {
+        MctsBot { rng, budget }
+    }
+
+    /// Runs one selection, expansion, playout and backpropagation, leaving
+    /// `scratch` as it found it.
+    fn iterate(&mut self, nodes: &mut Vec<Node>, scratch: &mut GameState, scores: &[u32]) {
+        let mut gains = vec![0u32; scratch.player_count() as usize];
+        let mut path: Vec<Move> = Vec::new();
+        let mut node = 0;
+
+        // Follow the most promising children down to a node with moves left to try
+        while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() {
+            node = best_child(nodes, node);
+            let player_move = nodes[node].player_move.expect("only the root has no move");
+            play(scratch, player_move, &mut gains);
+            path.push(player_move);
+        }
+
+        // Add one untried move to the tree
+        if !nodes[node].untried.is_empty() {
+            let index = self.rng.below(nodes[node].untried.len());
+            let player_move = nodes[node].untried.swap_remove(index);
+            let player = scratch.current_player();
+            play(scratch, player_move, &mut gains);
+            path.push(player_move);
+
+            let untried = if scratch.is_over() {
+                Vec::new()
+            } else {
+                candidates(scratch, &path, &mut self.rng)
+            };
+            nodes.push(Node {
+                player_move: Some(player_move),
+                player,
+                parent: Some(node),
+                children: Vec::new(),
+                untried,
+                visits: 0,
+                reward: 0.0,
+            });
+            let child = nodes.len() - 1;
+            nodes[node].children.push(child);
+            node = child;
+        }
+
+        let played = playout(scratch, &path, &mut gains, &mut self.rng);
+
+        let rewards = rewards(scratch.is_over(), scores, &gains);
+        let mut current = Some(node);
+        while let Some(index) = current {
+            let node = &mut nodes[index];
+            node.visits += 1;
+            if node.player > 0 {
+                node.reward += rewards[(node.player - 1) as usize];
+            }
+            current = node.parent;
+        }
+
+        for _ in 0..path.len() + played {
+            scratch.undo();
+        }
+    }
+}
+
+impl Bot for MctsBot {
+    fn choose_move(&mut self, state: &GameState) -> Move {
+        // Playouts misjudge who keeps control at the end, so small two player
+        // endgames are solved exactly instead
+        if state.player_count() == 2 && state.remaining_edges() <= solver::QUICK_SOLVE_EDGES {
+            if let Ok(solution) = solver::solve(state) {
+                return solution.best_move;
+            }
+        }
+
+        // The root looks at every move, however big the board
+        let root_moves = prune(state, state.legal_moves(), &mut self.rng);
+        if root_moves.len() == 1 {
+            return root_moves[0];
+        }
+
+        let mut nodes = vec![Node {
+            player_move: None,
+            player: 0,
+            parent: None,
+            children: Vec::new(),
+            untried: root_moves,
+            visits: 0,
+            reward: 0.0,
+        }];
+        let mut scratch = state.clone();
+        let scores = state.scores();
+        let started = Instant::now();
+        let mut iterations = 0;
+        loop {
+            let done = match self.budget {
+                Budget::Iterations(limit) => iterations >= limit,
+                Budget::Time(time) => iterations > 0 && started.elapsed() >= time,
+            };
+            if done {
+                break;
+            }
+            self.iterate(&mut nodes, &mut scratch, &scores);
+            iterations += 1;
+        }
+
+        // The most visited move is the most reliable
+        let best = nodes[0]
+            .children
+            .iter()
+            .max_by_key(|child| nodes[**child].visits)
+            .expect("the root is expanded on the first iteration");
+
+        nodes[*best].player_move.expect("only the root has no move")
+    }
+}
+
+/// A finished playout rewards the winners, sharing the reward on a tie. One
+/// cut short rewards each player with their share of the boxes taken.
+fn rewards(finished: bool, scores: &[u32], gains: &[u32]) -> Vec<f64> {
+    if finished {
+        let totals: Vec<u32> = scores.iter().zip(gains).map(|(s, g)| s + g).collect();
+        let best = totals.iter().copied().max().unwrap_or(0);
+        let winners = totals.iter().filter(|total| **total == best).count() as f64;
+        return totals
+            .iter()
+            .map(|total| if *total == best { 1.0 / winners } else { 0.0 })
+            .collect();
+    }
+
+    let taken: u32 = gains.iter().sum();
+    gains
+        .iter()
+        .map(|gain| {
+            if taken == 0 {
+                1.0 / gains.len() as f64
+            } else {
+                *gain as f64 / taken as f64
+            }
+        })
+        .collect()
+}
+
+fn play(state: &mut GameState, player_move: Move, gains: &mut [u32]) {
+    let outcome = state
+        .apply_move(player_move)
+        .expect("the search only plays legal moves");
+    gains[(outcome.player - 1) as usize] += outcome.completed.len() as u32;
+}
+
+fn best_child(nodes: &[Node], node: usize) -> usize {
+    let parent_visits = (nodes[node].visits.max(1) as f64).ln();
+    let score = |child: usize| {
+        let child = &nodes[child];
+        if child.visits == 0 {
+            return f64::INFINITY;
+        }
+        let visits = child.visits as f64;
+        child.reward / visits + EXPLORATION * (parent_visits / visits).sqrt()
+    };
+
+    nodes[node]
+        .children
+        .iter()
+        .copied()
+        .max_by(|a, b| score(*a).total_cmp(&score(*b)))
+        .expect("only called on expanded nodes")
+}
+
+/// The moves a tree node tries. Small boards are scanned in full; on big
+/// ones a random sample is taken along with any captures the moves on the
+/// way here opened up.
+fn candidates(state: &GameState, path: &[Move], rng: &mut Rng) -> Vec<Move> {
+    if state.remaining_edges() <= FULL_SCAN {
+        return prune(state, state.legal_moves(), rng);
+    }
+
+    let mut moves = captures_near(state, path);
+    for _ in 0..SAMPLES {
+        if let Some(edge) = random_edge(state, rng) {
+            if !moves.contains(&edge) {
+                moves.push(edge);
+            }
+        }
+    }
+
+    prune(state, moves, rng)
+}
+
+/// Cuts a list of legal moves down to those worth searching: a capture that
+/// sets nothing up on its own, otherwise every capture and double-dealing
+/// move, otherwise a few safe moves, otherwise the smallest sacrifices.
+fn prune(state: &GameState, moves: Vec<Move>, rng: &mut Rng) -> Vec<Move> {
+    let mut captures = Vec::new();
+    let mut safe = Vec::new();
+    let mut rest = Vec::new();
+    for m in moves {
+        if state.completes(m) > 0 {
+            if !state.gives_third_side(m) {
+                return vec![m];
+            }
+            captures.push(m);
+        } else if !state.gives_third_side(m) {
+            safe.push(m);
+        } else {
+            rest.push(m);
+        }
+    }
+
+    if !captures.is_empty() {
+        let declines = double_deals(state, &captures);
+        captures.extend(declines);
+        return captures;
+    }
+    if !safe.is_empty() {
+        shuffle(&mut safe, rng);
+        safe.truncate(WIDTH);
+        return safe;
+    }
+    shuffle(&mut rest, rng);
+    rest.truncate(SAMPLES);
+    rest.sort_by_key(|m| sacrifice_size(state, *m));
+    rest.truncate(WIDTH);
+
+    rest
+}
+
+fn shuffle(moves: &mut [Move], rng: &mut Rng) {
+    for index in (1..moves.len()).rev() {
+        moves.swap(index, rng.below(index + 1));
+    }
+}
+
+/// The last edge of every box next to these edges that has three sides.
+fn captures_near(state: &GameState, edges: &[Move]) -> Vec<Move> {
+    let mut captures = Vec::new();
+    for edge in edges {
+        for (y, x) in state.adjacent_boxes(*edge) {
+            if state.sides(y, x) == 3 {
+                captures.extend(state.open_edges(y, x));
+            }
+        }
+    }
+
+    captures
+}
+
+/// A random undrawn edge, or `None` if the one picked was already drawn.
+fn random_edge(state: &GameState, rng: &mut Rng) -> Option<Move> {
+    let (height, width) = (state.height(), state.width());
+    let edge = if rng.below(2) == 0 {
+        Move::new(
+            rng.below(height),
+            rng.below(width - 1),
+            Direction::Horizontal,
+        )
+    } else {
+        Move::new(rng.below(height - 1), rng.below(width), Direction::Vertical)
+    };
+
+    (!state.has_edge(edge)).then_some(edge)
+}
+
+/// Plays on from `scratch` taking any box offered and otherwise drawing a
+/// random edge that gives nothing away, if one can be found. Returns the
+/// number of moves played.
+fn playout(scratch: &mut GameState, path: &[Move], gains: &mut [u32], rng: &mut Rng) -> usize {
+    let mut pending = captures_near(scratch, path);
+    let mut played = 0;
+    while played < PLAYOUT_DEPTH && !scratch.is_over() {
+        let mut player_move = None;
+        while let Some(capture) = pending.pop() {
+            if !scratch.has_edge(capture) && scratch.completes(capture) > 0 {
+                player_move = Some(capture);
+                break;
+            }
+        }
+        let player_move = match player_move {
+            Some(capture) => capture,
+            None => playout_move(scratch, rng),
+        };
+
+        play(scratch, player_move, gains);
+        pending.extend(captures_near(scratch, &[player_move]));
+        played += 1;
+    }
+
+    played
+}
+
+fn playout_move(state: &GameState, rng: &mut Rng) -> Move {
+    if state.remaining_edges() <= FULL_SCAN {
+        let moves = state.legal_moves();
+        let safe: Vec<Move> = moves
+            .iter()
+            .copied()
+            .filter(|m| !state.gives_third_side(*m))
+            .collect();
+        if let Some(safe_move) = rng.choose(&safe) {
+            return *safe_move;
+        }
+        // Give away as little as possible out of a few tries
+        return (0..SACRIFICE_TRIES)
+            .map(|_| moves[rng.below(moves.len())])
+            .min_by_key(|m| sacrifice_size(state, *m))
+            .expect("the game is not over");
+    }
+
+    // Sample rather than scan, settling for any edge if nothing safe turns up
+    let mut fallback = None;
+    for _ in 0..SAMPLES {
+        if let Some(edge) = random_edge(state, rng) {
+            if !state.gives_third_side(edge) {
+                return edge;
+            }
+            fallback.get_or_insert(edge);
+        }
+    }
+
+    fallback.unwrap_or_else(|| {
+        *rng.choose(&state.legal_moves())
+            .expect("the game is not over")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Direction::{Horizontal, Vertical};
+
+    fn position(height: u16, width: u16, player_count: u8, moves: &[Move]) -> GameState {
+        let mut state = GameState::new(height, width, player_count);
+        for player_move in moves {
+            state.apply_move(*player_move).unwrap();
+        }
+
+        state
+    }
+
+    /// A 5x5 board with a chain of four boxes along the top, the first of
+    /// them ready to take. It is too big for the bot to hand to the solver.
+    fn capture() -> GameState {
+        let mut moves: Vec<Move> = (0..2)
+            .flat_map(|y| (0..4).map(move |x| Move::new(y, x, Horizontal)))
+            .collect();
+        moves.push(Move::new(0, 0, Vertical));
+
+        position(5, 5, 2, &moves)
+    }
+
+    #[test]
+    fn takes_an_obvious_capture() {
+        let state = capture();
+        assert!(state.remaining_edges() > solver::QUICK_SOLVE_EDGES);
+        for seed in 0..5 {
+            let mut bot = MctsBot::new(Rng::new(seed), Budget::Iterations(200));
+            assert_eq!(bot.choose_move(&state), Move::new(0, 1, Vertical));
+        }
+    }
+
+    #[test]
+    fn iterations_repeat_for_a_seed() {
+        let state = position(5, 5, 3, &[Move::new(2, 2, Horizontal)]);
+        let choose =
+            |seed| MctsBot::new(Rng::new(seed), Budget::Iterations(300)).choose_move(&state);
+
+        for seed in 0..3 {
+            assert_eq!(choose(seed), choose(seed));
+        }
+    }
+
+    #[test]
+    fn plays_the_last_edge() {
+        for player_count in [2, 3] {
+            let mut state = GameState::new(3, 3, player_count);
+            let last = Move::new(2, 1, Horizontal);
+            for player_move in state.legal_moves() {
+                if player_move != last {
+                    state.apply_move(player_move).unwrap();
+                }
+            }
+            assert_eq!(state.legal_moves(), vec![last]);
+
+            let mut bot = MctsBot::new(Rng::new(1), Budget::default());
+            assert_eq!(bot.choose_move(&state), last);
+        }
+    }
+}
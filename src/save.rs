@@ -0,0 +1,1199 @@
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::bot::BotKind;
+use crate::configuration::{
+    default_player_symbol, valid_board_dimension, valid_player_count, valid_player_symbol, Config,
+    Player, PlayerKind, Rules,
+};
+use crate::error::{BoxesError, RowKind, SaveParseError};
+use crate::json::Json;
+use crate::state::{Direction, GameState, LoggedMove, Move};
+
+/// The version written to the first line of new saves.
+pub const SAVE_VERSION: u32 = 2;
+
+/// The start of the first line of a versioned save. Legacy saves start with
+/// the player turn instead.
+const SAVE_HEADER: &str = "boxes save";
+
+/// How a save file is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// The line based text format, either versioned or legacy.
+    Text,
+    /// A single JSON document holding the full game state.
+    Json,
+}
+
+impl SaveFormat {
+    /// Picks the format from the file extension, defaulting to text.
+    pub fn from_path(path: &str) -> SaveFormat {
+        let extension = Path::new(path).extension().and_then(|e| e.to_str());
+        if extension.is_some_and(|e| e.eq_ignore_ascii_case("json")) {
+            SaveFormat::Json
+        } else {
+            SaveFormat::Text
+        }
+    }
+}
+
+impl FromStr for SaveFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(SaveFormat::Text),
+            "json" => Ok(SaveFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Loads a save in any format. `dimensions` holds the height, width and
+/// player count given on the command line. Legacy saves need them, and
+/// versioned saves must agree with them when they are given. Without a
+/// `format` it is picked from the file extension.
+pub(crate) fn load(
+    path: &str,
+    dimensions: Option<(u16, u16, u8)>,
+    format: Option<SaveFormat>,
+) -> Result<Config, BoxesError> {
+    if format.unwrap_or(SaveFormat::from_path(path)) == SaveFormat::Json {
+        let text = fs::read_to_string(path).map_err(BoxesError::SaveIo)?;
+        return Ok(load_json(&text, dimensions)?);
+    }
+
+    let lines = read_lines(path)?;
+    if is_versioned(&lines) {
+        return Ok(load_versioned(&lines, dimensions)?);
+    }
+
+    let Some((height, width, player_count)) = dimensions else {
+        return Err(SaveParseError::MissingHeader { field: "size" }.into());
+    };
+    let mut config = Config::new(height, width, player_count);
+    load_legacy(&lines, &mut config)?;
+
+    Ok(config)
+}
+
+/// Loads a save for `boxes replay`, which only names the file. For legacy
+/// saves the board size is taken from the shape of the grid, and the player
+/// count is the highest player named in it, which is enough to replay the
+/// moves in its log.
+pub(crate) fn load_replay(path: &str) -> Result<Config, BoxesError> {
+    if SaveFormat::from_path(path) == SaveFormat::Json {
+        return load(path, None, Some(SaveFormat::Json));
+    }
+
+    let lines = read_lines(path)?;
+    if is_versioned(&lines) {
+        return Ok(load_versioned(&lines, None)?);
+    }
+
+    // The first edge row is one shorter than the width, and the grid holds
+    // height * 3 - 1 lines including the turn
+    let grid_lines = lines
+        .iter()
+        .take_while(|line| line.trim() != "moves" && !line.trim().is_empty())
+        .count();
+    let width = lines.get(1).map_or(0, |line| line.trim().len() + 1);
+    let height = (grid_lines + 1) / 3;
+    if lines.is_empty() {
+        return Err(SaveParseError::Empty.into());
+    }
+    // A bad width shows in the first edge row, and a bad height where the
+    // grid stops
+    let bad_line = if !valid_board_dimension(width as u16) {
+        Some(2)
+    } else if !valid_board_dimension(height as u16) {
+        Some(grid_lines + 1)
+    } else {
+        None
+    };
+    if let Some(line) = bad_line {
+        return Err(SaveParseError::InvalidGrid {
+            line,
+            height,
+            width,
+        }
+        .into());
+    }
+
+    // Every number in the turn line and cells, and the first in each log entry, names a player
+    let cells = lines.iter().take(grid_lines).skip(height * 2);
+    let log_players = lines
+        .iter()
+        .skip(grid_lines + 1)
+        .filter_map(|line| line.split(' ').next());
+    let players = lines
+        .iter()
+        .take(1)
+        .map(String::as_str)
+        .chain(cells.flat_map(|line| line.split(',')))
+        .chain(log_players);
+    let mut player_count: u8 = 2;
+    for player in players {
+        if let Ok(player) = player.trim().parse::<u8>() {
+            player_count = player_count.max(player.min(100));
+        }
+    }
+
+    let mut config = Config::new(height as u16, width as u16, player_count);
+    load_legacy(&lines, &mut config)?;
+
+    Ok(config)
+}
+
+/// Writes the game in the current save format.
+pub fn write(state: &GameState, players: &[Player]) -> String {
+    let mut save_contents = format!("{SAVE_HEADER} {SAVE_VERSION}\n");
+    save_contents.push_str(&format!("size {} {}\n", state.height(), state.width()));
+    save_contents.push_str(&format!("players {}\n", state.player_count()));
+    for (index, player) in players.iter().enumerate() {
+        save_contents.push_str(&format!("player {} {}\n", index + 1, player.name));
+    }
+    for (index, player) in players.iter().enumerate() {
+        // Only symbols that differ from the default are written
+        if player.symbol != default_player_symbol(index as u8 + 1) {
+            save_contents.push_str(&format!("symbol {} {}\n", index + 1, player.symbol));
+        }
+    }
+    for (index, player) in players.iter().enumerate() {
+        if let PlayerKind::Bot(kind) = player.kind {
+            save_contents.push_str(&format!("bot {} {kind}\n", index + 1));
+        }
+    }
+    save_contents.push_str(&format!("rules {}\n", format_rules(state.rules())));
+    let hints: Vec<String> = state.hints_used().iter().map(u32::to_string).collect();
+    save_contents.push_str(&format!("hints {}\n", hints.join(" ")));
+    save_contents.push_str(&format!("turn {}\n", state.current_player()));
+
+    // Process edge data
+    save_contents.push_str("edges\n");
+    for row in edge_rows(state) {
+        save_contents.push_str(&row);
+        save_contents.push('\n');
+    }
+
+    // Process cell data
+    save_contents.push_str("cells\n");
+    for row in cell_rows(state) {
+        save_contents.push_str(&row);
+        save_contents.push('\n');
+    }
+
+    // Append the move log so the game can be replayed or undone after loading
+    save_contents.push_str("moves\n");
+    for record in state.history() {
+        save_contents.push_str(&LoggedMove::from(record).to_string());
+        save_contents.push('\n');
+    }
+
+    save_contents
+}
+
+/// Writes the game as a JSON document.
+pub fn write_json(state: &GameState, players: &[Player]) -> String {
+    let mut save_contents = to_json(state, players).to_string();
+    save_contents.push('\n');
+
+    save_contents
+}
+
+/// The full game state as JSON: dimensions, players, rules, the player to
+/// move, edges, cell owners and the move history.
+pub fn to_json(state: &GameState, players: &[Player]) -> Json {
+    let horizontal: Vec<Json> = (0..state.height())
+        .map(|y| {
+            (0..state.width() - 1)
+                .map(|x| state.has_edge(Move::new(y, x, Direction::Horizontal)))
+                .collect::<Vec<bool>>()
+                .into()
+        })
+        .collect();
+    let vertical: Vec<Json> = (0..state.height() - 1)
+        .map(|y| {
+            (0..state.width())
+                .map(|x| state.has_edge(Move::new(y, x, Direction::Vertical)))
+                .collect::<Vec<bool>>()
+                .into()
+        })
+        .collect();
+    let cells: Vec<Json> = (0..state.height() - 1)
+        .map(|y| {
+            (0..state.width() - 1)
+                .map(|x| state.owner(y, x))
+                .collect::<Vec<u8>>()
+                .into()
+        })
+        .collect();
+    let players: Vec<Json> = players
+        .iter()
+        .enumerate()
+        .map(|(index, player)| {
+            let mut fields = vec![("name", player.name.as_str().into())];
+            if player.symbol != default_player_symbol(index as u8 + 1) {
+                fields.push(("symbol", player.symbol.as_str().into()));
+            }
+            if let PlayerKind::Bot(kind) = player.kind {
+                fields.push(("bot", kind.to_string().into()));
+            }
+            Json::object(fields)
+        })
+        .collect();
+    let moves: Vec<Json> = state
+        .history()
+        .iter()
+        .map(|record| logged_move_to_json(&LoggedMove::from(record)))
+        .collect();
+
+    Json::object(vec![
+        ("format", "boxes".into()),
+        ("version", SAVE_VERSION.into()),
+        ("height", state.height().into()),
+        ("width", state.width().into()),
+        ("players", Json::Array(players)),
+        ("rules", rules_to_json(state.rules())),
+        ("hints", state.hints_used().to_vec().into()),
+        ("turn", state.current_player().into()),
+        (
+            "edges",
+            Json::object(vec![
+                ("horizontal", Json::Array(horizontal)),
+                ("vertical", Json::Array(vertical)),
+            ]),
+        ),
+        ("cells", Json::Array(cells)),
+        ("moves", Json::Array(moves)),
+    ])
+}
+
+/// A move log entry as `{"player", "y", "x", "dir", "boxes"}`.
+pub fn logged_move_to_json(entry: &LoggedMove) -> Json {
+    let direction = match entry.player_move.direction {
+        Direction::Horizontal => "h",
+        Direction::Vertical => "v",
+    };
+
+    Json::object(vec![
+        ("player", entry.player.into()),
+        ("y", entry.player_move.y.into()),
+        ("x", entry.player_move.x.into()),
+        ("dir", direction.into()),
+        ("boxes", entry.boxes.into()),
+    ])
+}
+
+pub fn rules_to_json(rules: &Rules) -> Json {
+    Json::object(vec![
+        ("undo", rules.allow_undo.into()),
+        ("hints", rules.allow_hints.into()),
+    ])
+}
+
+fn load_json(text: &str, dimensions: Option<(u16, u16, u8)>) -> Result<Config, SaveParseError> {
+    let document = Json::parse(text).map_err(SaveParseError::InvalidJson)?;
+    let invalid_field = |field: &str| SaveParseError::InvalidField {
+        field: field.to_string(),
+    };
+    let number = |field: &str| {
+        document
+            .get(field)
+            .and_then(Json::as_u64)
+            .ok_or_else(|| invalid_field(field))
+    };
+
+    if document.get("format").and_then(Json::as_str) != Some("boxes") {
+        return Err(invalid_field("format"));
+    }
+    let version = number("version")?;
+    if version != SAVE_VERSION as u64 {
+        return Err(SaveParseError::UnsupportedVersion {
+            found: version.to_string(),
+        });
+    }
+
+    // The size and player count must match the command line if given
+    let height = number("height")?;
+    let width = number("width")?;
+    if !valid_board_dimension(height.min(u16::MAX as u64) as u16) {
+        return Err(invalid_field("height"));
+    }
+    if !valid_board_dimension(width.min(u16::MAX as u64) as u16) {
+        return Err(invalid_field("width"));
+    }
+    let (height, width) = (height as u16, width as u16);
+    let players = document
+        .get("players")
+        .and_then(Json::as_array)
+        .ok_or_else(|| invalid_field("players"))?;
+    if !valid_player_count(players.len().min(u8::MAX as usize) as u8) {
+        return Err(invalid_field("players"));
+    }
+    let player_count = players.len() as u8;
+    if let Some((given_height, given_width, given_player_count)) = dimensions {
+        if (given_height, given_width) != (height, width) {
+            return Err(SaveParseError::HeaderMismatch { field: "size" });
+        }
+        if given_player_count != player_count {
+            return Err(SaveParseError::HeaderMismatch { field: "players" });
+        }
+    }
+
+    let mut config = Config::new(height, width, player_count);
+    for (index, player) in players.iter().enumerate() {
+        match player.get("name").and_then(Json::as_str) {
+            Some(name) if !name.trim().is_empty() => config.players[index].name = name.to_string(),
+            _ => return Err(invalid_field(&format!("players[{index}].name"))),
+        }
+        if let Some(symbol) = player.get("symbol") {
+            match symbol.as_str() {
+                Some(symbol) if valid_player_symbol(symbol) => {
+                    config.players[index].symbol = symbol.to_string()
+                }
+                _ => return Err(invalid_field(&format!("players[{index}].symbol"))),
+            }
+        }
+        if let Some(kind) = player.get("bot") {
+            let kind = kind.as_str().and_then(|kind| kind.parse::<BotKind>().ok());
+            let kind = kind.ok_or_else(|| invalid_field(&format!("players[{index}].bot")))?;
+            config.players[index].kind = PlayerKind::Bot(kind);
+        }
+    }
+    if let Some(rules) = document.get("rules") {
+        config.rules = rules_from_json(rules).ok_or_else(|| invalid_field("rules"))?;
+    }
+    if let Some(hints) = document.get("hints") {
+        let counts: Option<Vec<u32>> = hints.as_array().and_then(|counts| {
+            counts
+                .iter()
+                .map(|count| count.as_u64().and_then(|count| u32::try_from(count).ok()))
+                .collect()
+        });
+        match counts {
+            Some(counts) if counts.len() == player_count as usize => config.hints_used = counts,
+            _ => return Err(invalid_field("hints")),
+        }
+    }
+    match number("turn") {
+        Ok(turn) if turn >= 1 && turn <= player_count as u64 => config.player_turn = turn as u8,
+        _ => return Err(invalid_field("turn")),
+    }
+
+    // Convert the grid into the same rows a text save holds
+    let edges = document
+        .get("edges")
+        .ok_or_else(|| invalid_field("edges"))?;
+    let horizontal = json_rows(edges.get("horizontal"), height as usize, width as usize - 1)
+        .ok_or_else(|| invalid_field("edges.horizontal"))?;
+    let vertical = json_rows(edges.get("vertical"), height as usize - 1, width as usize)
+        .ok_or_else(|| invalid_field("edges.vertical"))?;
+    for (y, row) in horizontal.iter().enumerate() {
+        config.board_edges.push(
+            edge_row_from_json(row)
+                .ok_or_else(|| invalid_field(&format!("edges.horizontal[{y}]")))?,
+        );
+        if let Some(row) = vertical.get(y) {
+            config.board_edges.push(
+                edge_row_from_json(row)
+                    .ok_or_else(|| invalid_field(&format!("edges.vertical[{y}]")))?,
+            );
+        }
+    }
+    let cells = json_rows(
+        document.get("cells"),
+        height as usize - 1,
+        width as usize - 1,
+    )
+    .ok_or_else(|| invalid_field("cells"))?;
+    for (y, row) in cells.iter().enumerate() {
+        let mut owners: Vec<String> = Vec::new();
+        for (x, cell) in row.iter().enumerate() {
+            match cell.as_u64() {
+                Some(owner) if owner <= player_count as u64 => owners.push(owner.to_string()),
+                _ => return Err(invalid_field(&format!("cells[{y}][{x}]"))),
+            }
+        }
+        config.board_cells.push(owners.join(","));
+    }
+
+    // Check the move history fits the board
+    if let Some(moves) = document.get("moves") {
+        let moves = moves.as_array().ok_or_else(|| invalid_field("moves"))?;
+        for (index, entry) in moves.iter().enumerate() {
+            let entry = logged_move_from_json(entry)
+                .ok_or_else(|| invalid_field(&format!("moves[{index}]")))?;
+            config.move_log.push(entry);
+        }
+        let mut state = GameState::build(&Config {
+            move_log: Vec::new(),
+            ..config.clone()
+        });
+        if let Err(index) = state.restore_history(&config.move_log) {
+            return Err(invalid_field(&format!("moves[{index}]")));
+        }
+    }
+
+    Ok(config)
+}
+
+/// The rows of a JSON grid, if it has `rows` arrays of `columns` values each.
+fn json_rows(value: Option<&Json>, rows: usize, columns: usize) -> Option<Vec<&[Json]>> {
+    let values = value?.as_array()?;
+    if values.len() != rows {
+        return None;
+    }
+    values
+        .iter()
+        .map(|row| row.as_array().filter(|row| row.len() == columns))
+        .collect()
+}
+
+fn edge_row_from_json(row: &[Json]) -> Option<String> {
+    row.iter()
+        .map(|edge| match edge.as_bool()? {
+            true => Some('1'),
+            false => Some('0'),
+        })
+        .collect()
+}
+
+pub fn logged_move_from_json(entry: &Json) -> Option<LoggedMove> {
+    let player = entry.get("player")?.as_u64()?;
+    let player_move = move_from_json(entry)?;
+    let boxes = entry.get("boxes")?.as_u64()?;
+
+    Some(LoggedMove {
+        player: u8::try_from(player).ok()?,
+        player_move,
+        boxes: boxes as usize,
+    })
+}
+
+/// Reads a move written as `{"y", "x", "dir"}` where `dir` is `h` or `v`.
+pub fn move_from_json(value: &Json) -> Option<Move> {
+    let y = value.get("y")?.as_u64()?;
+    let x = value.get("x")?.as_u64()?;
+    let direction = match value.get("dir")?.as_str()? {
+        "h" => Direction::Horizontal,
+        "v" => Direction::Vertical,
+        _ => return None,
+    };
+
+    Some(Move::new(y as usize, x as usize, direction))
+}
+
+pub fn rules_from_json(value: &Json) -> Option<Rules> {
+    let mut rules = Rules::default();
+    let Json::Object(fields) = value else {
+        return None;
+    };
+    for (key, value) in fields {
+        match key.as_str() {
+            "undo" => rules.allow_undo = value.as_bool()?,
+            "hints" => rules.allow_hints = value.as_bool()?,
+            _ => return None,
+        }
+    }
+
+    Some(rules)
+}
+
+fn read_lines(path: &str) -> Result<Vec<String>, BoxesError> {
+    let file_content = File::open(path).map_err(BoxesError::SaveIo)?;
+
+    // Attempt to read lines into a vector of strings
+    BufReader::new(&file_content)
+        .lines()
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(BoxesError::SaveIo)
+}
+
+fn is_versioned(lines: &[String]) -> bool {
+    lines
+        .first()
+        .is_some_and(|line| line.starts_with(SAVE_HEADER))
+}
+
+/// The number of lines once blank lines at the end of the file are ignored,
+/// so hand-edited saves still load.
+fn content_line_count(lines: &[String]) -> usize {
+    lines.len()
+        - lines
+            .iter()
+            .rev()
+            .take_while(|l| l.trim().is_empty())
+            .count()
+}
+
+fn load_legacy(lines: &[String], config: &mut Config) -> Result<(), SaveParseError> {
+    let line_count = content_line_count(lines);
+    if line_count == 0 {
+        return Err(SaveParseError::Empty);
+    }
+
+    // Parse player turn
+    if lines[0].trim().is_empty() {
+        return Err(SaveParseError::MissingTurn);
+    }
+    config.player_turn = parse_turn(&lines[0], 1, config.player_count)?;
+
+    let mut index = load_edge_rows(lines, 1, line_count, config)?;
+    index = load_cell_rows(lines, index, line_count, config)?;
+    load_move_log(lines, index, line_count, config)
+}
+
+fn load_versioned(
+    lines: &[String],
+    dimensions: Option<(u16, u16, u8)>,
+) -> Result<Config, SaveParseError> {
+    let line_count = content_line_count(lines);
+
+    // Check the format version
+    let version = lines[0][SAVE_HEADER.len()..].trim();
+    if version.parse::<u32>() != Ok(SAVE_VERSION) {
+        return Err(SaveParseError::UnsupportedVersion {
+            found: version.to_string(),
+        });
+    }
+
+    // Read header fields until the grid starts
+    let mut size: Option<(u16, u16)> = None;
+    let mut player_count: Option<u8> = None;
+    let mut names: Vec<(usize, u8, String)> = Vec::new();
+    let mut symbols: Vec<(usize, u8, String)> = Vec::new();
+    let mut bots: Vec<(usize, u8, BotKind)> = Vec::new();
+    let mut rules = Rules::default();
+    let mut hints: Option<(usize, Vec<u32>)> = None;
+    let mut turn: Option<(usize, &str)> = None;
+    let mut index = 1;
+    while index < line_count && lines[index].trim() != "edges" {
+        let line = index + 1;
+        let data = lines[index].trim();
+        let invalid_header = || SaveParseError::InvalidHeader {
+            line,
+            found: data.to_string(),
+        };
+        let (key, value) = data.split_once(' ').unwrap_or((data, ""));
+        match key {
+            "size" => {
+                let values: Vec<Option<u16>> =
+                    value.split(' ').map(|v| v.parse::<u16>().ok()).collect();
+                let [Some(height), Some(width)] = values[..] else {
+                    return Err(invalid_header());
+                };
+                if !valid_board_dimension(height) || !valid_board_dimension(width) {
+                    return Err(invalid_header());
+                }
+                size = Some((height, width));
+            }
+            "players" => match value.parse::<u8>() {
+                Ok(count) if valid_player_count(count) => player_count = Some(count),
+                _ => return Err(invalid_header()),
+            },
+            "player" => {
+                let Some((number, name)) = value.split_once(' ') else {
+                    return Err(invalid_header());
+                };
+                match number.parse::<u8>() {
+                    Ok(number) if number > 0 && !name.trim().is_empty() => {
+                        names.push((line, number, name.trim().to_string()))
+                    }
+                    _ => return Err(invalid_header()),
+                }
+            }
+            "symbol" => {
+                let Some((number, symbol)) = value.split_once(' ') else {
+                    return Err(invalid_header());
+                };
+                match number.parse::<u8>() {
+                    Ok(number) if number > 0 && valid_player_symbol(symbol) => {
+                        symbols.push((line, number, symbol.to_string()))
+                    }
+                    _ => return Err(invalid_header()),
+                }
+            }
+            "bot" => {
+                let Some((number, kind)) = value.split_once(' ') else {
+                    return Err(invalid_header());
+                };
+                match (number.parse::<u8>(), kind.parse::<BotKind>()) {
+                    (Ok(number), Ok(kind)) if number > 0 => bots.push((line, number, kind)),
+                    _ => return Err(invalid_header()),
+                }
+            }
+            "rules" => {
+                rules = parse_rules(value).ok_or_else(invalid_header)?;
+            }
+            "hints" => {
+                let counts: Option<Vec<u32>> =
+                    value.split(' ').map(|v| v.parse::<u32>().ok()).collect();
+                hints = Some((line, counts.ok_or_else(invalid_header)?));
+            }
+            "turn" => turn = Some((line, value)),
+            _ => return Err(invalid_header()),
+        }
+        index += 1;
+    }
+
+    // The size and player count are required, and must match the command line if given
+    let Some((height, width)) = size else {
+        return Err(SaveParseError::MissingHeader { field: "size" });
+    };
+    let Some(player_count) = player_count else {
+        return Err(SaveParseError::MissingHeader { field: "players" });
+    };
+    if let Some((given_height, given_width, given_player_count)) = dimensions {
+        if (given_height, given_width) != (height, width) {
+            return Err(SaveParseError::HeaderMismatch { field: "size" });
+        }
+        if given_player_count != player_count {
+            return Err(SaveParseError::HeaderMismatch { field: "players" });
+        }
+    }
+
+    let mut config = Config::new(height, width, player_count);
+    config.rules = rules;
+    for (line, number, name) in names {
+        let Some(player) = config.players.get_mut(number as usize - 1) else {
+            return Err(SaveParseError::InvalidHeader {
+                line,
+                found: lines[line - 1].clone(),
+            });
+        };
+        player.name = name;
+    }
+    for (line, number, symbol) in symbols {
+        let Some(player) = config.players.get_mut(number as usize - 1) else {
+            return Err(SaveParseError::InvalidHeader {
+                line,
+                found: lines[line - 1].clone(),
+            });
+        };
+        player.symbol = symbol;
+    }
+    for (line, number, kind) in bots {
+        let Some(player) = config.players.get_mut(number as usize - 1) else {
+            return Err(SaveParseError::InvalidHeader {
+                line,
+                found: lines[line - 1].clone(),
+            });
+        };
+        player.kind = PlayerKind::Bot(kind);
+    }
+    // Older saves have no hint counts, so everyone starts from zero
+    if let Some((line, counts)) = hints {
+        if counts.len() != player_count as usize {
+            return Err(SaveParseError::InvalidHeader {
+                line,
+                found: lines[line - 1].clone(),
+            });
+        }
+        config.hints_used = counts;
+    }
+    let Some((line, turn)) = turn else {
+        return Err(SaveParseError::MissingHeader { field: "turn" });
+    };
+    config.player_turn = parse_turn(turn, line, player_count)?;
+
+    // The grid and move log follow, each introduced by a section line
+    expect_section(lines, index, line_count, "edges")?;
+    index = load_edge_rows(lines, index + 1, line_count, &mut config)?;
+    expect_section(lines, index, line_count, "cells")?;
+    index = load_cell_rows(lines, index + 1, line_count, &mut config)?;
+    load_move_log(lines, index, line_count, &mut config)?;
+
+    Ok(config)
+}
+
+fn expect_section(
+    lines: &[String],
+    index: usize,
+    line_count: usize,
+    section: &'static str,
+) -> Result<(), SaveParseError> {
+    if index < line_count && lines[index].trim() == section {
+        Ok(())
+    } else {
+        Err(SaveParseError::MissingSection {
+            line: index + 1,
+            section,
+        })
+    }
+}
+
+fn parse_turn(data: &str, line: usize, player_count: u8) -> Result<u8, SaveParseError> {
+    let valid_player_turns = 1..=player_count;
+    match data.trim().parse::<u8>() {
+        Ok(player_turn) if valid_player_turns.contains(&player_turn) => Ok(player_turn),
+        _ => Err(SaveParseError::InvalidTurn {
+            line,
+            found: data.to_string(),
+            player_count,
+        }),
+    }
+}
+
+/// Reads the edge rows starting at `index`, returning the index of the line
+/// after them. Line numbers are reported 1-based to match what a text editor
+/// shows.
+fn load_edge_rows(
+    lines: &[String],
+    index: usize,
+    line_count: usize,
+    config: &mut Config,
+) -> Result<usize, SaveParseError> {
+    let edge_rows = (config.height * 2 - 1) as usize;
+    for row in 0..edge_rows {
+        let line = index + row + 1;
+        let kind = if row % 2 == 0 {
+            RowKind::HorizontalEdges
+        } else {
+            RowKind::VerticalEdges
+        };
+        let Some(data) = lines.get(index + row).filter(|_| index + row < line_count) else {
+            return Err(SaveParseError::MissingRow { line, kind });
+        };
+
+        validate_edge_row(data, line, kind, config.width)?;
+        config.board_edges.push(data.to_string());
+    }
+
+    Ok(index + edge_rows)
+}
+
+/// Reads the cell rows starting at `index`, returning the index of the line
+/// after them.
+fn load_cell_rows(
+    lines: &[String],
+    index: usize,
+    line_count: usize,
+    config: &mut Config,
+) -> Result<usize, SaveParseError> {
+    let cell_rows = (config.height - 1) as usize;
+    for row in 0..cell_rows {
+        let line = index + row + 1;
+        let Some(data) = lines.get(index + row).filter(|_| index + row < line_count) else {
+            return Err(SaveParseError::MissingRow {
+                line,
+                kind: RowKind::Cells,
+            });
+        };
+
+        validate_cell_row(data, line, config.width, config.player_count)?;
+        config.board_cells.push(data.to_string());
+    }
+
+    Ok(index + cell_rows)
+}
+
+/// Reads the optional move log that follows the grid.
+fn load_move_log(
+    lines: &[String],
+    index: usize,
+    line_count: usize,
+    config: &mut Config,
+) -> Result<(), SaveParseError> {
+    if index >= line_count {
+        return Ok(());
+    }
+    if lines[index].trim() != "moves" {
+        return Err(SaveParseError::TrailingData { line: index + 1 });
+    }
+
+    let log_start = index + 1;
+    for (offset, data) in lines[log_start..line_count].iter().enumerate() {
+        let Ok(entry) = data.trim().parse::<LoggedMove>() else {
+            return Err(SaveParseError::InvalidLogEntry {
+                line: log_start + offset + 1,
+                found: data.to_string(),
+            });
+        };
+        config.move_log.push(entry);
+    }
+
+    // Replay the log backwards from the saved board to check it fits
+    let mut state = GameState::build(&Config {
+        move_log: Vec::new(),
+        ..config.clone()
+    });
+    if let Err(entry) = state.restore_history(&config.move_log) {
+        return Err(SaveParseError::InconsistentLogEntry {
+            line: log_start + entry + 1,
+        });
+    }
+
+    Ok(())
+}
+
+fn validate_edge_row(
+    data: &str,
+    line: usize,
+    kind: RowKind,
+    width: u16,
+) -> Result<(), SaveParseError> {
+    // Horizontal rows hold the edges between dots, vertical rows hold one per dot
+    let expected = match kind {
+        RowKind::HorizontalEdges => width as usize - 1,
+        _ => width as usize,
+    };
+    let actual = data.chars().count();
+    if expected != actual {
+        return Err(SaveParseError::RowLength {
+            line,
+            kind,
+            expected,
+            actual,
+        });
+    }
+
+    // Each edge is a single flag
+    for (column, c) in data.chars().enumerate() {
+        if c != '0' && c != '1' {
+            return Err(SaveParseError::InvalidEdge {
+                line,
+                column: column + 1,
+                found: c,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_cell_row(
+    data: &str,
+    line: usize,
+    width: u16,
+    player_count: u8,
+) -> Result<(), SaveParseError> {
+    // Remove commas and store complete numbers in vector
+    let split_string: Vec<&str> = data.split(',').collect();
+
+    // Check that cells in save can fit on game board width
+    let expected = width as usize - 1;
+    if expected != split_string.len() {
+        return Err(SaveParseError::RowLength {
+            line,
+            kind: RowKind::Cells,
+            expected,
+            actual: split_string.len(),
+        });
+    }
+
+    let mut column = 1;
+    for player in split_string {
+        // Attempt to convert the player cell entry to numeric
+        let Ok(player_as_numeric) = player.trim().parse::<u16>() else {
+            return Err(SaveParseError::InvalidCell {
+                line,
+                column,
+                found: player.to_string(),
+            });
+        };
+        // Players are numbered from 1, with 0 marking an unclaimed cell
+        if player_as_numeric > player_count as u16 {
+            return Err(SaveParseError::UnknownPlayer {
+                line,
+                column,
+                player: player_as_numeric,
+            });
+        }
+        // Skip past this entry and its trailing comma
+        column += player.chars().count() + 1;
+    }
+
+    Ok(())
+}
+
+/// Rules are written as space separated `key=value` pairs.
+fn format_rules(rules: &Rules) -> String {
+    let switch = |enabled: bool| if enabled { "on" } else { "off" };
+
+    format!(
+        "undo={} hints={}",
+        switch(rules.allow_undo),
+        switch(rules.allow_hints)
+    )
+}
+
+fn parse_rules(data: &str) -> Option<Rules> {
+    let mut rules = Rules::default();
+    for pair in data.split(' ').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=')?;
+        let enabled = match value {
+            "on" => true,
+            "off" => false,
+            _ => return None,
+        };
+        match key {
+            "undo" => rules.allow_undo = enabled,
+            "hints" => rules.allow_hints = enabled,
+            _ => return None,
+        }
+    }
+
+    Some(rules)
+}
+
+/// The edge rows of a board as written to text saves: `0` or `1` for each
+/// edge, alternating horizontal and vertical rows from the top.
+pub(crate) fn edge_rows(state: &GameState) -> Vec<String> {
+    let mut rows = Vec::new();
+    for y in 0..state.height() {
+        rows.push(
+            (0..state.width() - 1)
+                .map(|x| edge_flag(state, Move::new(y, x, Direction::Horizontal)))
+                .collect(),
+        );
+        if y < state.height() - 1 {
+            rows.push(
+                (0..state.width())
+                    .map(|x| edge_flag(state, Move::new(y, x, Direction::Vertical)))
+                    .collect(),
+            );
+        }
+    }
+
+    rows
+}
+
+/// The cell rows of a board as written to text saves: comma separated owners.
+pub(crate) fn cell_rows(state: &GameState) -> Vec<String> {
+    (0..state.height() - 1)
+        .map(|y| {
+            let row: Vec<String> = (0..state.width() - 1)
+                .map(|x| state.owner(y, x).to_string())
+                .collect();
+            row.join(",")
+        })
+        .collect()
+}
+
+fn edge_flag(state: &GameState, edge: Move) -> char {
+    if state.has_edge(edge) {
+        '1'
+    } else {
+        '0'
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    /// A game on three by three dots where player 1, Alice, has taken the
+    /// top left box and player 2, drawn as a crab, is to move.
+    fn game() -> (GameState, Vec<Player>) {
+        let mut state = GameState::new(3, 3, 2);
+        for (y, x, direction) in [
+            (0, 0, Direction::Horizontal),
+            (1, 0, Direction::Vertical),
+            (1, 0, Direction::Horizontal),
+            (0, 0, Direction::Vertical),
+            (0, 1, Direction::Vertical),
+            (2, 1, Direction::Horizontal),
+        ] {
+            state.apply_move(Move::new(y, x, direction)).unwrap();
+        }
+        let mut players = Config::new(3, 3, 2).players;
+        players[0].name = "Alice".to_string();
+        players[1].symbol = "🦀".to_string();
+
+        (state, players)
+    }
+
+    /// Checks that a loaded save holds the game it was written from.
+    fn assert_holds(config: &Config, state: &GameState, players: &[Player]) {
+        assert_eq!(
+            (config.height, config.width, config.player_count),
+            (3, 3, 2)
+        );
+        assert_eq!(config.player_turn, state.current_player());
+        assert_eq!(config.players, players);
+        assert_eq!(config.board_edges, ["10", "110", "10", "100", "01"]);
+        assert_eq!(config.board_cells, ["1,0", "0,0"]);
+        let history: Vec<LoggedMove> = state.history().iter().map(LoggedMove::from).collect();
+        assert_eq!(config.move_log, history);
+    }
+
+    #[test]
+    fn loads_a_legacy_save() {
+        let mut config = Config::new(2, 3, 2);
+        load_legacy(&lines("2\n11\n110\n11\n1,0\n\n"), &mut config).unwrap();
+
+        assert_eq!(config.player_turn, 2);
+        assert_eq!(config.board_edges, ["11", "110", "11"]);
+        assert_eq!(config.board_cells, ["1,0"]);
+        assert!(config.move_log.is_empty());
+    }
+
+    #[test]
+    fn reports_where_a_legacy_save_goes_wrong() {
+        let cases = [
+            ("", SaveParseError::Empty),
+            ("\n00", SaveParseError::MissingTurn),
+            (
+                "3\n00\n000\n00\n0,0",
+                SaveParseError::InvalidTurn {
+                    line: 1,
+                    found: "3".to_string(),
+                    player_count: 2,
+                },
+            ),
+            (
+                "1\n00\n0x0\n00\n0,0",
+                SaveParseError::InvalidEdge {
+                    line: 3,
+                    column: 2,
+                    found: 'x',
+                },
+            ),
+            (
+                "1\n00\n00\n00\n0,0",
+                SaveParseError::RowLength {
+                    line: 3,
+                    kind: RowKind::VerticalEdges,
+                    expected: 3,
+                    actual: 2,
+                },
+            ),
+            (
+                "1\n00\n000",
+                SaveParseError::MissingRow {
+                    line: 4,
+                    kind: RowKind::HorizontalEdges,
+                },
+            ),
+            (
+                "1\n00\n000\n00\n0,a",
+                SaveParseError::InvalidCell {
+                    line: 5,
+                    column: 3,
+                    found: "a".to_string(),
+                },
+            ),
+            (
+                "1\n00\n000\n00\n0,3",
+                SaveParseError::UnknownPlayer {
+                    line: 5,
+                    column: 3,
+                    player: 3,
+                },
+            ),
+            (
+                "1\n00\n000\n00\n0,0\nextra",
+                SaveParseError::TrailingData { line: 6 },
+            ),
+            (
+                "1\n00\n000\n00\n0,0\nmoves\n1 0 0 x 0",
+                SaveParseError::InvalidLogEntry {
+                    line: 7,
+                    found: "1 0 0 x 0".to_string(),
+                },
+            ),
+            // The board has no edges for this move to have drawn
+            (
+                "1\n00\n000\n00\n0,0\nmoves\n2 0 0 h 0",
+                SaveParseError::InconsistentLogEntry { line: 7 },
+            ),
+        ];
+
+        for (text, expected) in cases {
+            let mut config = Config::new(2, 3, 2);
+            assert_eq!(
+                load_legacy(&lines(text), &mut config),
+                Err(expected),
+                "{text:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_a_versioned_save() {
+        let (state, players) = game();
+        let saved = lines(&write(&state, &players));
+        let config = load_versioned(&saved, None).unwrap();
+
+        assert_holds(&config, &state, &players);
+        assert!(load_versioned(&saved, Some((3, 3, 2))).is_ok());
+        assert!(matches!(
+            load_versioned(&saved, Some((3, 4, 2))),
+            Err(SaveParseError::HeaderMismatch { field: "size" })
+        ));
+    }
+
+    #[test]
+    fn reports_where_a_versioned_save_goes_wrong() {
+        let (state, players) = game();
+        let saved = write(&state, &players);
+        let broken = |from: &str, to: &str| {
+            assert!(saved.contains(from));
+            load_versioned(&lines(&saved.replacen(from, to, 1)), None).unwrap_err()
+        };
+
+        assert_eq!(
+            broken("boxes save 2", "boxes save 9"),
+            SaveParseError::UnsupportedVersion {
+                found: "9".to_string()
+            }
+        );
+        assert_eq!(
+            broken("players 2\n", "players 2\ncolour blue\n"),
+            SaveParseError::InvalidHeader {
+                line: 4,
+                found: "colour blue".to_string(),
+            }
+        );
+        assert_eq!(
+            broken("size 3 3\n", ""),
+            SaveParseError::MissingHeader { field: "size" }
+        );
+        assert!(matches!(
+            broken("cells\n", ""),
+            SaveParseError::MissingSection {
+                section: "cells",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn round_trips_a_json_save() {
+        let (state, players) = game();
+        let saved = write_json(&state, &players);
+        let config = load_json(&saved, None).unwrap();
+
+        assert_holds(&config, &state, &players);
+        assert!(matches!(
+            load_json(&saved, Some((3, 3, 3))),
+            Err(SaveParseError::HeaderMismatch { field: "players" })
+        ));
+    }
+
+    #[test]
+    fn names_the_bad_field_of_a_json_save() {
+        let (state, players) = game();
+        let saved = write_json(&state, &players);
+
+        assert_eq!(
+            load_json(&saved.replacen("\"width\":3", "\"width\":1", 1), None).unwrap_err(),
+            SaveParseError::InvalidField {
+                field: "width".to_string()
+            }
+        );
+        assert!(matches!(
+            load_json(&saved[..saved.len() / 2], None),
+            Err(SaveParseError::InvalidJson(_))
+        ));
+    }
+}
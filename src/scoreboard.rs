@@ -0,0 +1,127 @@
+/// A player's final position in a game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Standing {
+    pub player: u8,
+    /// The number of boxes the player has claimed.
+    pub boxes: u32,
+    /// The player's position, starting at 1. Tied players share a rank and the
+    /// rank after a tie is skipped, so two players tied for first are followed
+    /// by third.
+    pub rank: u8,
+}
+
+/// Player standings ordered by boxes claimed, with ties broken by player
+/// number so the order is the same on every run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scoreboard {
+    standings: Vec<Standing>,
+}
+
+impl Scoreboard {
+    /// Ranks players from their scores, where `scores[0]` belongs to player 1.
+    pub fn build(scores: &[u32]) -> Scoreboard {
+        let mut standings: Vec<Standing> = scores
+            .iter()
+            .enumerate()
+            .map(|(index, boxes)| Standing {
+                player: index as u8 + 1,
+                boxes: *boxes,
+                rank: 0,
+            })
+            .collect();
+        standings.sort_by(|a, b| b.boxes.cmp(&a.boxes).then(a.player.cmp(&b.player)));
+
+        // Competition ranking: players on equal boxes share the higher rank
+        for position in 0..standings.len() {
+            standings[position].rank =
+                if position > 0 && standings[position].boxes == standings[position - 1].boxes {
+                    standings[position - 1].rank
+                } else {
+                    position as u8 + 1
+                };
+        }
+
+        Scoreboard { standings }
+    }
+
+    pub fn standings(&self) -> &[Standing] {
+        &self.standings
+    }
+
+    /// The player numbers sharing first place, in player order.
+    pub fn winners(&self) -> Vec<u8> {
+        self.standings
+            .iter()
+            .filter(|standing| standing.rank == 1)
+            .map(|standing| standing.player)
+            .collect()
+    }
+
+    /// Whether more than one player shares first place.
+    pub fn is_tie(&self) -> bool {
+        self.winners().len() > 1
+    }
+
+    /// The number of boxes claimed by `player`.
+    pub fn boxes(&self, player: u8) -> u32 {
+        self.standings
+            .iter()
+            .find(|standing| standing.player == player)
+            .map_or(0, |standing| standing.boxes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Direction, GameState, Move};
+
+    fn ranks(scoreboard: &Scoreboard) -> Vec<(u8, u32, u8)> {
+        scoreboard
+            .standings()
+            .iter()
+            .map(|standing| (standing.player, standing.boxes, standing.rank))
+            .collect()
+    }
+
+    #[test]
+    fn unclaimed_boxes_never_count() {
+        // Player 2 claims the top left box and the other three stay open
+        let mut state = GameState::new(3, 3, 2);
+        for (y, x, direction) in [
+            (0, 0, Direction::Horizontal),
+            (1, 0, Direction::Horizontal),
+            (0, 0, Direction::Vertical),
+            (0, 1, Direction::Vertical),
+        ] {
+            state.apply_move(Move::new(y, x, direction)).unwrap();
+        }
+        let scoreboard = state.scoreboard();
+
+        assert_eq!(ranks(&scoreboard), vec![(2, 1, 1), (1, 0, 2)]);
+        assert_eq!(scoreboard.boxes(1) + scoreboard.boxes(2), 1);
+        assert_eq!(scoreboard.winners(), vec![2]);
+    }
+
+    #[test]
+    fn tied_players_share_a_rank() {
+        let scoreboard = Scoreboard::build(&[3, 5, 5, 1]);
+
+        assert_eq!(
+            ranks(&scoreboard),
+            vec![(2, 5, 1), (3, 5, 1), (1, 3, 3), (4, 1, 4)]
+        );
+        assert_eq!(scoreboard.winners(), vec![2, 3]);
+        assert!(scoreboard.is_tie());
+    }
+
+    #[test]
+    fn ties_are_listed_in_player_order() {
+        let scoreboard = Scoreboard::build(&[2, 4, 2, 4, 2]);
+        let players: Vec<u8> = scoreboard.standings().iter().map(|s| s.player).collect();
+
+        assert_eq!(players, vec![2, 4, 1, 3, 5]);
+        assert_eq!(scoreboard, Scoreboard::build(&[2, 4, 2, 4, 2]));
+        assert_eq!(Scoreboard::build(&[0, 0, 0]).winners(), vec![1, 2, 3]);
+    }
+}
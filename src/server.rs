@@ -0,0 +1,942 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{stdout, BufRead, BufReader, Write};
+use std::iter;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bot::BotKind;
+use crate::configuration::{Config, PlayerKind};
+use crate::error::BoxesError;
+use crate::game::{Computers, Game};
+use crate::mcts::Budget;
+use crate::rng;
+use crate::save;
+use crate::state::{GameState, LoggedMove, Move};
+
+/// The port `boxes serve` listens on unless `--port` says otherwise.
+pub const DEFAULT_PORT: u16 = 7777;
+
+/// How long a seat may stay empty after a disconnect before its player
+/// forfeits, unless `--forfeit-after` says otherwise.
+pub const DEFAULT_FORFEIT_AFTER: Duration = Duration::from_secs(60);
+
+/// How long a write to a client may block before the client is dropped, so
+/// one that stops reading cannot hold up every game on the server.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The most unfinished games a server hosts, and the most of them one
+/// connection may have created, before `create` is refused.
+const MAX_OPEN_GAMES: usize = 64;
+const MAX_GAMES_PER_CONNECTION: usize = 4;
+
+/// The most dots a side of a game created over the network may have.
+pub(crate) const MAX_CREATE_DOTS: u16 = 20;
+
+/// The most an MCTS bot in a game created over the network may think about
+/// each move.
+const MAX_CREATE_ITERATIONS: u32 = 20_000;
+const MAX_CREATE_THINK_TIME: Duration = Duration::from_secs(2);
+
+/// The arguments `create` accepts, shown when they are wrong.
+const CREATE_USAGE: &str = "create [--no-undo] [--no-hints] [--bot player=kind] [--seed n] [--name player=name] [--symbol player=symbol] height width playercount";
+
+/// The settings for `boxes serve`, which hosts games for players who join
+/// with `boxes connect`.
+///
+/// The server holds the only copy of each game and talks to clients in lines
+/// of text. Clients start in the lobby, where they can send:
+///
+/// - `list` for a `game <id>: <status>` line for each game being played.
+/// - `create [options] height width playercount` to add a game, answered
+///   with `created <id>`. The options are the rules, bots, seed, names and
+///   symbols of a terminal game. Boards are limited to 20 dots a side and
+///   MCTS bots to 20000 iterations or 2 seconds a move. A connection may
+///   have 4 games open at once, and the server 64.
+/// - `join [id]` to take the next free seat of a game, or of the first game
+///   with one.
+/// - `join <id> <token>` to take back a seat after a disconnect, with the
+///   token sent when the seat was first taken. Until the player forfeits,
+///   nobody else can take it.
+/// - `watch [id]` to follow a game, or the first game, as a spectator. Any
+///   number of spectators may watch, from any point in the game.
+/// - `archive` for an `archived <id>: <result>` line for each finished game.
+/// - `history <id>` for a `history <move>` line for each move of a game.
+///
+/// Lobby replies end with `ok` or `error <text>`, or with `seat` or
+/// `watching <id>` once the client has joined or is watching a game. Games
+/// start once every human seat is taken. In a game the server sends:
+///
+/// - `seat <id> <player> <symbol> <token> <name>` to a client when it takes
+///   a seat.
+/// - `board <row>` for each row of the board, to a client when it joins or
+///   starts watching and to everyone after every move.
+/// - `scores <text>` after each board.
+/// - `played <symbol> y x h|v <name>` after each move.
+/// - `turn <player> <symbol> <name>` when a player is to move.
+/// - `message <text>` for joins, disconnects, pauses and forfeits.
+/// - `error <text>` when a client's line was rejected.
+/// - `result <line>` for each line of the final standings, then `over`,
+///   after which the connection is closed.
+///
+/// Seated clients send `move y x h|v` on their turn.
+#[derive(Debug, Clone)]
+pub struct Server {
+    pub port: u16,
+    pub forfeit_after: Duration,
+    /// A directory to write each finished game to as a JSON save.
+    pub archive_dir: Option<String>,
+    /// A game to open when the server starts, built from the same arguments
+    /// as a terminal game.
+    pub config: Option<Config>,
+}
+
+impl Server {
+    /// Builds the settings for `boxes serve [--port n] [--forfeit-after s]
+    /// [--archive dir] [[options] height width playercount]`, where the
+    /// options are those of a terminal game.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Server, BoxesError> {
+        let mut args = args;
+        let program = args.next().ok_or(BoxesError::Usage)?;
+        let mut port = DEFAULT_PORT;
+        let mut forfeit_after = DEFAULT_FORFEIT_AFTER;
+        let mut archive_dir = None;
+        let mut game_args = Vec::new();
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--port" => {
+                    let value = args.next().and_then(|value| value.parse::<u16>().ok());
+                    port = value.ok_or(BoxesError::Usage)?;
+                }
+                "--forfeit-after" => {
+                    let value = args.next().and_then(|value| value.parse::<u64>().ok());
+                    forfeit_after = Duration::from_secs(value.ok_or(BoxesError::Usage)?);
+                }
+                "--archive" => archive_dir = Some(args.next().ok_or(BoxesError::Usage)?),
+                _ => game_args.push(arg),
+            }
+        }
+
+        let config = if game_args.is_empty() {
+            None
+        } else {
+            Some(Config::build(iter::once(program).chain(game_args))?)
+        };
+
+        Ok(Server {
+            port,
+            forfeit_after,
+            archive_dir,
+            config,
+        })
+    }
+}
+
+/// Something that happened on a connection, numbered in the order clients
+/// connected, or a move a game's bots and engines chose.
+enum Event {
+    Joined(usize, TcpStream),
+    Line(usize, String),
+    Left(usize),
+    Computed(u32, Result<Move, String>),
+}
+
+/// Every game on the server and the connections still choosing one.
+struct Lobby {
+    tables: BTreeMap<u32, Table>,
+    archive: Vec<Archived>,
+    next_id: u32,
+    /// Connections in the lobby.
+    idle: HashMap<usize, TcpStream>,
+    /// The game each playing or watching connection is at.
+    locations: HashMap<usize, u32>,
+    forfeit_after: Duration,
+    archive_dir: Option<String>,
+    /// Handed to each game's computer players to send their moves back.
+    events: Sender<Event>,
+}
+
+/// A finished game, kept so its result and moves can still be looked up.
+struct Archived {
+    id: u32,
+    summary: String,
+    history: Vec<String>,
+}
+
+/// A game being hosted and the clients connected to it.
+struct Table {
+    game: Game,
+    /// The connection that created the game, or `None` for the server's own.
+    creator: Option<usize>,
+    /// Players and spectators, who are sent every update.
+    clients: HashMap<usize, TcpStream>,
+    spectators: HashSet<usize>,
+    /// The connection holding each human seat, indexed from player 1. Bot and
+    /// engine seats are never held.
+    seats: Vec<Option<usize>>,
+    /// When each empty seat was left during play, for forfeits. Such a seat
+    /// is kept for whoever holds its token.
+    left_at: Vec<Option<Instant>>,
+    /// The secret for taking back each seat, handed out with it.
+    tokens: Vec<Option<String>>,
+    forfeit_after: Duration,
+    started: bool,
+    /// Positions for the game's bots and engines to choose a move in, on the
+    /// game's own thread so they never hold up the rest of the server.
+    computers: Sender<GameState>,
+    /// Whether a computer player is choosing a move.
+    thinking: bool,
+}
+
+/// Hosts games until the server is stopped. Each game starts once every
+/// human seat is taken and pauses whenever one is empty.
+pub fn run(server: Server) -> Result<String, BoxesError> {
+    let listener = TcpListener::bind(("0.0.0.0", server.port)).map_err(BoxesError::Network)?;
+    let port = listener.local_addr().map_err(BoxesError::Network)?.port();
+    println!("Listening on port {port}");
+    stdout().flush().map_err(BoxesError::System)?;
+
+    serve(listener, server)
+}
+
+/// Hosts games on connections from a listener that is already bound, which
+/// lets tests pick a free port. `server.port` is not used.
+pub fn serve(listener: TcpListener, server: Server) -> Result<String, BoxesError> {
+    let (events, inbox) = mpsc::channel();
+    let accepted = events.clone();
+    thread::spawn(move || accept(listener, accepted));
+
+    let mut lobby = Lobby {
+        tables: BTreeMap::new(),
+        archive: Vec::new(),
+        next_id: 1,
+        idle: HashMap::new(),
+        locations: HashMap::new(),
+        forfeit_after: server.forfeit_after,
+        archive_dir: server.archive_dir,
+        events,
+    };
+    if let Some(config) = server.config {
+        lobby.open(config, None)?;
+        lobby.archive_finished();
+    }
+
+    loop {
+        let deadline = lobby
+            .tables
+            .values()
+            .filter_map(Table::forfeit_deadline)
+            .min();
+        let event = match deadline {
+            Some(deadline) => {
+                inbox.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => inbox.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match event {
+            Ok(Event::Joined(id, stream)) => {
+                lobby.idle.insert(id, stream);
+            }
+            Ok(Event::Line(id, line)) => lobby.receive(id, &line),
+            Ok(Event::Left(id)) => lobby.leave(id),
+            Ok(Event::Computed(game_id, outcome)) => {
+                if let Some(table) = lobby.tables.get_mut(&game_id) {
+                    table.computed(outcome);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                for table in lobby.tables.values_mut() {
+                    table.forfeit_absent();
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        lobby.archive_finished();
+    }
+
+    Ok(String::new())
+}
+
+/// Chooses moves for a game's bots and engines until the game is over and
+/// its table is dropped.
+fn think(
+    game_id: u32,
+    mut computers: Computers,
+    positions: Receiver<GameState>,
+    events: Sender<Event>,
+) {
+    for state in positions {
+        let Some(outcome) = computers.choose_move(&state) else {
+            continue;
+        };
+        if events.send(Event::Computed(game_id, outcome)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Accepts connections for as long as the server runs, reading each on its
+/// own thread and passing everything on as events.
+fn accept(listener: TcpListener, events: Sender<Event>) {
+    for (id, stream) in listener.incoming().enumerate() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+            continue;
+        }
+        let Ok(reader) = stream.try_clone() else {
+            continue;
+        };
+        if events.send(Event::Joined(id, stream)).is_err() {
+            return;
+        }
+        let events = events.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if events.send(Event::Line(id, line)).is_err() {
+                    return;
+                }
+            }
+            let _ = events.send(Event::Left(id));
+        });
+    }
+}
+
+impl Lobby {
+    /// Adds a game and starts it if it has no human seats to fill.
+    fn open(&mut self, config: Config, creator: Option<usize>) -> Result<u32, BoxesError> {
+        let mut game = Game::build(config)?;
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let (computers, positions) = mpsc::channel();
+        let players = game.take_computers();
+        let events = self.events.clone();
+        thread::spawn(move || think(id, players, positions, events));
+
+        let mut table = Table {
+            seats: vec![None; game.players.len()],
+            left_at: vec![None; game.players.len()],
+            tokens: vec![None; game.players.len()],
+            game,
+            creator,
+            clients: HashMap::new(),
+            spectators: HashSet::new(),
+            forfeit_after: self.forfeit_after,
+            started: false,
+            computers,
+            thinking: false,
+        };
+        table.start_if_full();
+        self.tables.insert(id, table);
+
+        Ok(id)
+    }
+
+    fn receive(&mut self, id: usize, line: &str) {
+        if let Some(game_id) = self.locations.get(&id) {
+            if let Some(table) = self.tables.get_mut(game_id) {
+                table.receive(id, line);
+            }
+            return;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => (),
+            ["list"] => {
+                let games: Vec<String> = self
+                    .tables
+                    .iter()
+                    .map(|(game_id, table)| format!("game {game_id}: {}", table.status()))
+                    .collect();
+                for game in games {
+                    self.reply(id, &game);
+                }
+                self.reply(id, "ok");
+            }
+            ["create", args @ ..] => {
+                let config = self.check_room(id).and_then(|_| create_config(args));
+                match config {
+                    Ok(config) => match self.open(config, Some(id)) {
+                        Ok(game_id) => {
+                            self.reply(id, &format!("created {game_id}"));
+                            self.reply(id, "ok");
+                        }
+                        Err(e) => self.reply(id, &format!("error {e}")),
+                    },
+                    Err(message) => self.reply(id, &format!("error {message}")),
+                }
+            }
+            ["join"] => {
+                let open = self
+                    .tables
+                    .iter()
+                    .find(|(_, table)| table.free_seat().is_some());
+                match open.map(|(game_id, _)| *game_id) {
+                    Some(game_id) => self.enter(id, game_id, false),
+                    None => self.reply(id, "error no game has a free seat"),
+                }
+            }
+            ["watch"] => match self.tables.keys().next().copied() {
+                Some(game_id) => self.enter(id, game_id, true),
+                None => self.reply(id, "error no games are being played"),
+            },
+            ["join" | "watch", game_id] => {
+                match game_id
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|g| self.tables.contains_key(g))
+                {
+                    Some(game_id) => self.enter(id, game_id, words[0] == "watch"),
+                    None => self.reply(id, "error no such game"),
+                }
+            }
+            ["join", game_id, token] => {
+                match game_id
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|g| self.tables.contains_key(g))
+                {
+                    Some(game_id) => self.rejoin(id, game_id, token),
+                    None => self.reply(id, "error no such game"),
+                }
+            }
+            ["archive"] => {
+                let games: Vec<String> = self
+                    .archive
+                    .iter()
+                    .map(|archived| format!("archived {}: {}", archived.id, archived.summary))
+                    .collect();
+                for game in games {
+                    self.reply(id, &game);
+                }
+                self.reply(id, "ok");
+            }
+            ["history", game_id] => {
+                let game_id = game_id.parse::<u32>().ok();
+                let history = match game_id.and_then(|g| self.tables.get(&g)) {
+                    Some(table) => Some(table.history()),
+                    None => self
+                        .archive
+                        .iter()
+                        .find(|archived| Some(archived.id) == game_id)
+                        .map(|archived| archived.history.clone()),
+                };
+                let Some(history) = history else {
+                    self.reply(id, "error no such game");
+                    return;
+                };
+                for line in history {
+                    self.reply(id, &format!("history {line}"));
+                }
+                self.reply(id, "ok");
+            }
+            _ => self.reply(id, "error unknown command"),
+        }
+    }
+
+    /// Refuses another game when the server or the connection asking for it
+    /// already has as many open as it may.
+    fn check_room(&self, id: usize) -> Result<(), String> {
+        let created = self
+            .tables
+            .values()
+            .filter(|table| table.creator == Some(id))
+            .count();
+        if created >= MAX_GAMES_PER_CONNECTION {
+            return Err(format!(
+                "you already have {MAX_GAMES_PER_CONNECTION} open games"
+            ));
+        }
+        if self.tables.len() >= MAX_OPEN_GAMES {
+            return Err(format!(
+                "the server already has {MAX_OPEN_GAMES} open games"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Moves a connection from the lobby to a game as a player or spectator.
+    fn enter(&mut self, id: usize, game_id: u32, watch: bool) {
+        let Some(table) = self.tables.get_mut(&game_id) else {
+            return;
+        };
+        if !watch && table.free_seat().is_none() {
+            self.reply(id, "error the game is full");
+            return;
+        }
+        let Some(stream) = self.idle.remove(&id) else {
+            return;
+        };
+
+        self.locations.insert(id, game_id);
+        if watch {
+            table.watch(id, game_id, stream);
+        } else if let Some(seat) = table.free_seat() {
+            table.join(id, game_id, seat, stream);
+        }
+    }
+
+    /// Gives a seat left during play back to the connection with its token.
+    fn rejoin(&mut self, id: usize, game_id: u32, token: &str) {
+        let Some(table) = self.tables.get_mut(&game_id) else {
+            return;
+        };
+        let Some(seat) = table.reserved_seat(token) else {
+            self.reply(id, "error no seat is waiting for that token");
+            return;
+        };
+        let Some(stream) = self.idle.remove(&id) else {
+            return;
+        };
+
+        self.locations.insert(id, game_id);
+        table.join(id, game_id, seat, stream);
+    }
+
+    fn leave(&mut self, id: usize) {
+        self.idle.remove(&id);
+        let Some(game_id) = self.locations.remove(&id) else {
+            return;
+        };
+        if let Some(table) = self.tables.get_mut(&game_id) {
+            table.leave(id);
+        }
+    }
+
+    /// Sends the final standings of finished games, closes their
+    /// connections and moves them to the archive.
+    fn archive_finished(&mut self) {
+        let finished: Vec<u32> = self
+            .tables
+            .iter()
+            .filter(|(_, table)| table.game.is_finished())
+            .map(|(game_id, _)| *game_id)
+            .collect();
+        for game_id in finished {
+            let Some(mut table) = self.tables.remove(&game_id) else {
+                continue;
+            };
+            let standings = table.finish();
+            self.locations.retain(|_, location| *location != game_id);
+
+            if let Some(dir) = &self.archive_dir {
+                let path = format!("{dir}/game-{game_id}.json");
+                let contents = save::write_json(&table.game.state, &table.game.players);
+                if let Err(e) = fs::write(&path, contents) {
+                    eprintln!("Error archiving game {game_id} to {path}: {e}");
+                }
+            }
+
+            let winners = standings
+                .lines()
+                .find_map(|line| line.strip_prefix("Winner(s): "))
+                .unwrap_or("none");
+            self.archive.push(Archived {
+                id: game_id,
+                summary: format!("{}, won by {winners}", table.size()),
+                history: table.history(),
+            });
+        }
+    }
+
+    /// Writes a line to a connection in the lobby, dropping it if the write
+    /// fails.
+    fn reply(&mut self, id: usize, line: &str) {
+        if let Some(stream) = self.idle.get_mut(&id) {
+            if !write_line(stream, line) {
+                self.idle.remove(&id);
+            }
+        }
+    }
+}
+
+/// Builds a game from the arguments to `create`. Only rules, bots and a seed
+/// may be given, so clients cannot load files or run programs on the server.
+fn create_config(args: &[&str]) -> Result<Config, String> {
+    let mut positional = 0;
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match *arg {
+            "--no-undo" | "--no-hints" => (),
+            "--bot" | "--seed" | "--name" | "--symbol" => {
+                args_iter.next();
+            }
+            flag if flag.starts_with("--") => return Err(format!("usage: {CREATE_USAGE}")),
+            _ => positional += 1,
+        }
+    }
+    if positional != 3 {
+        return Err(format!("usage: {CREATE_USAGE}"));
+    }
+
+    let args = iter::once("boxes")
+        .chain(args.iter().copied())
+        .map(String::from);
+    let config = Config::build(args).map_err(|e| match e {
+        BoxesError::Usage => format!("usage: {CREATE_USAGE}"),
+        e => e.to_string(),
+    })?;
+
+    // Keep one client from tying up the server's processors
+    if config.height > MAX_CREATE_DOTS || config.width > MAX_CREATE_DOTS {
+        return Err(format!(
+            "games created here have at most {MAX_CREATE_DOTS} dots a side"
+        ));
+    }
+    let too_slow = config.players.iter().any(|player| match player.kind {
+        PlayerKind::Bot(BotKind::Mcts(Budget::Iterations(iterations))) => {
+            iterations > MAX_CREATE_ITERATIONS
+        }
+        PlayerKind::Bot(BotKind::Mcts(Budget::Time(time))) => time > MAX_CREATE_THINK_TIME,
+        _ => false,
+    });
+    if too_slow {
+        return Err(format!(
+            "MCTS bots created here think for at most {MAX_CREATE_ITERATIONS} iterations or {} second(s)",
+            MAX_CREATE_THINK_TIME.as_secs()
+        ));
+    }
+
+    Ok(config)
+}
+
+impl Table {
+    /// The first human seat nobody holds or is coming back to.
+    fn free_seat(&self) -> Option<usize> {
+        (0..self.seats.len()).find(|seat| {
+            self.seats[*seat].is_none()
+                && self.left_at[*seat].is_none()
+                && self.game.players[*seat].kind == PlayerKind::Human
+        })
+    }
+
+    /// The seat left during play whose token this is.
+    fn reserved_seat(&self, token: &str) -> Option<usize> {
+        (0..self.seats.len()).find(|seat| {
+            self.left_at[*seat].is_some() && self.tokens[*seat].as_deref() == Some(token)
+        })
+    }
+
+    fn join(&mut self, id: usize, game_id: u32, seat: usize, stream: TcpStream) {
+        self.clients.insert(id, stream);
+        self.seats[seat] = Some(id);
+        self.left_at[seat] = None;
+        let token = self.tokens[seat].get_or_insert_with(rng::token).clone();
+        let player = seat as u8 + 1;
+        let symbol = self.game.player_symbol(player).to_string();
+        let name = self.game.player_name(player).to_string();
+        self.send(
+            id,
+            &format!("seat {game_id} {player} {symbol} {token} {name}"),
+        );
+        let label = self.game.player_label(player);
+        self.broadcast(&format!("message {label} joined"));
+
+        if self.started {
+            self.send_position(id);
+            if !self.is_paused() {
+                self.broadcast("message The game continues");
+                self.advance();
+            }
+        } else {
+            self.start_if_full();
+        }
+    }
+
+    /// Adds a spectator and catches them up with the game so far.
+    fn watch(&mut self, id: usize, game_id: u32, stream: TcpStream) {
+        self.clients.insert(id, stream);
+        self.spectators.insert(id);
+        self.send(id, &format!("watching {game_id}"));
+        if !self.started {
+            return;
+        }
+
+        self.send_position(id);
+        if !self.is_paused() && !self.game.is_finished() {
+            let turn = self.turn_line();
+            self.send(id, &turn);
+        }
+    }
+
+    fn leave(&mut self, id: usize) {
+        self.clients.remove(&id);
+        self.spectators.remove(&id);
+        let Some(seat) = self.seats.iter().position(|held| *held == Some(id)) else {
+            return;
+        };
+
+        self.seats[seat] = None;
+        let label = self.game.player_label(seat as u8 + 1);
+        if self.started {
+            self.left_at[seat] = Some(Instant::now());
+            self.broadcast(&format!(
+                "message {label} disconnected, the game is paused for up to {} second(s)",
+                self.forfeit_after.as_secs()
+            ));
+        } else {
+            // The next player to take the seat gets a new token
+            self.tokens[seat] = None;
+            self.broadcast(&format!("message {label} left"));
+        }
+    }
+
+    fn receive(&mut self, id: usize, line: &str) {
+        if self.spectators.contains(&id) {
+            self.send(id, "error spectators cannot move");
+            return;
+        }
+        let Some(seat) = self.seats.iter().position(|held| *held == Some(id)) else {
+            return;
+        };
+        let player = seat as u8 + 1;
+        let Some(text) = line.strip_prefix("move ") else {
+            self.send(id, "error unknown command");
+            return;
+        };
+        if !self.started {
+            self.send(id, "error the game has not started");
+            return;
+        }
+        if self.is_paused() {
+            self.send(id, "error the game is paused");
+            return;
+        }
+        if self.game.state.current_player() != player {
+            self.send(id, "error it is not your turn");
+            return;
+        }
+
+        let outcome = text
+            .trim()
+            .parse::<Move>()
+            .and_then(|player_move| self.game.state.apply_move(player_move).map(|_| player_move));
+        match outcome {
+            Ok(player_move) => {
+                self.announce_move(player, player_move);
+                self.advance();
+            }
+            Err(cause) => {
+                // Ask again so the client prompts for another move
+                self.send(id, &format!("error {cause}"));
+                let turn = self.turn_line();
+                self.send(id, &turn);
+            }
+        }
+    }
+
+    fn start_if_full(&mut self) {
+        if self.is_paused() {
+            return;
+        }
+        self.started = true;
+        self.broadcast("message The game begins");
+        for id in self.clients.keys().copied().collect::<Vec<_>>() {
+            self.send_position(id);
+        }
+        self.advance();
+    }
+
+    /// Asks the game's thread for a move when a bot or engine is to move, or
+    /// tells everyone whose turn it is when a human is.
+    fn advance(&mut self) {
+        if self.game.is_finished() || self.thinking {
+            return;
+        }
+        let player = self.game.state.current_player();
+        if self.game.players[(player - 1) as usize].kind == PlayerKind::Human {
+            let turn = self.turn_line();
+            self.broadcast(&turn);
+        } else if self.computers.send(self.game.state.clone()).is_ok() {
+            self.thinking = true;
+        }
+    }
+
+    /// Plays the move a bot or engine chose and carries on with the game,
+    /// unless a seat emptied while it was thinking.
+    fn computed(&mut self, outcome: Result<Move, String>) {
+        self.thinking = false;
+        if self.game.is_finished() {
+            return;
+        }
+        let player = self.game.state.current_player();
+        match self.game.play_computer_move(outcome) {
+            Some(player_move) => self.announce_move(player, player_move),
+            None => {
+                let label = self.game.player_label(player);
+                let cause = self.game.forfeit.as_ref().map_or("", |(_, cause)| cause);
+                let message = format!("message {label} forfeits: {cause}");
+                self.broadcast(&message);
+            }
+        }
+        if !self.is_paused() {
+            self.advance();
+        }
+    }
+
+    fn announce_move(&mut self, player: u8, player_move: Move) {
+        let symbol = self.game.player_symbol(player).to_string();
+        let name = self.game.player_name(player).to_string();
+        self.broadcast(&format!("played {symbol} {player_move} {name}"));
+        for id in self.clients.keys().copied().collect::<Vec<_>>() {
+            self.send_position(id);
+        }
+    }
+
+    /// The line telling everyone whose turn it is.
+    fn turn_line(&self) -> String {
+        let player = self.game.state.current_player();
+        format!(
+            "turn {player} {} {}",
+            self.game.player_symbol(player),
+            self.game.player_name(player)
+        )
+    }
+
+    /// Whether a human seat is empty.
+    fn is_paused(&self) -> bool {
+        (0..self.seats.len()).any(|seat| {
+            self.seats[seat].is_none() && self.game.players[seat].kind == PlayerKind::Human
+        })
+    }
+
+    /// The time the longest empty seat forfeits at.
+    fn forfeit_deadline(&self) -> Option<Instant> {
+        self.left_at
+            .iter()
+            .flatten()
+            .min()
+            .map(|left_at| *left_at + self.forfeit_after)
+    }
+
+    fn forfeit_absent(&mut self) {
+        let now = Instant::now();
+        let Some(seat) = (0..self.left_at.len())
+            .find(|seat| self.left_at[*seat].is_some_and(|left| left + self.forfeit_after <= now))
+        else {
+            return;
+        };
+
+        let player = seat as u8 + 1;
+        self.game.forfeit = Some((player, "disconnected".to_string()));
+        let label = self.game.player_label(player);
+        self.broadcast(&format!("message {label} forfeits: disconnected"));
+    }
+
+    /// Sends everyone the final standings and closes every connection.
+    fn finish(&mut self) -> String {
+        let standings = self.game.standings();
+        for line in standings.lines() {
+            self.broadcast(&format!("result {line}"));
+        }
+        self.broadcast("over");
+        for stream in self.clients.values() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+
+        standings
+    }
+
+    /// Sends the board and the scores.
+    fn send_position(&mut self, id: usize) {
+        for row in self.game.board_lines() {
+            self.send(id, &format!("board {row}"));
+        }
+        let scores: Vec<String> = self
+            .game
+            .state
+            .scores()
+            .iter()
+            .enumerate()
+            .map(|(index, score)| format!("{} {score}", self.game.player_label(index as u8 + 1)))
+            .collect();
+        self.send(id, &format!("scores Scores: {}", scores.join(", ")));
+    }
+
+    /// The board size and players, as shown in the lobby.
+    fn size(&self) -> String {
+        let state = &self.game.state;
+        format!(
+            "{}x{} dots, {} players",
+            state.height(),
+            state.width(),
+            state.player_count()
+        )
+    }
+
+    fn status(&self) -> String {
+        let humans = (0..self.seats.len())
+            .filter(|seat| self.game.players[*seat].kind == PlayerKind::Human)
+            .count();
+        let taken = self.seats.iter().flatten().count();
+        let progress = if !self.started {
+            "waiting for players"
+        } else if self.is_paused() {
+            "paused"
+        } else {
+            "playing"
+        };
+
+        format!(
+            "{}, {taken} of {humans} seats taken, {} watching, {progress}",
+            self.size(),
+            self.spectators.len()
+        )
+    }
+
+    /// Every move so far, numbered from 1, with the player who made it and
+    /// the boxes it completed.
+    fn history(&self) -> Vec<String> {
+        self.game
+            .state
+            .history()
+            .iter()
+            .enumerate()
+            .map(|(index, record)| {
+                let entry = LoggedMove::from(record);
+                format!(
+                    "{}. {} {} ({} box(es))",
+                    index + 1,
+                    self.game.player_label(entry.player),
+                    entry.player_move,
+                    entry.boxes
+                )
+            })
+            .collect()
+    }
+
+    /// Writes a line to one client, dropping it if the write fails. Its seat
+    /// is given up when its reader notices the connection is closed.
+    fn send(&mut self, id: usize, line: &str) {
+        if let Some(stream) = self.clients.get_mut(&id) {
+            if !write_line(stream, line) {
+                self.clients.remove(&id);
+            }
+        }
+    }
+
+    fn broadcast(&mut self, line: &str) {
+        self.clients.retain(|_, stream| write_line(stream, line));
+    }
+}
+
+/// Writes a line to a client and says whether it arrived. A client whose
+/// write fails or times out is shut down, so its reader reports it gone.
+fn write_line(stream: &mut TcpStream, line: &str) -> bool {
+    if writeln!(stream, "{line}").is_ok() {
+        return true;
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+
+    false
+}
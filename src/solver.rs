@@ -0,0 +1,524 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+
+use crate::rng::Rng;
+use crate::state::{Direction, GameState, Move};
+
+/// The solver packs the board into one bit per edge, so boards with more
+/// edges than this cannot be solved. A board of 4 by 5 boxes has 49.
+pub const MAX_EDGES: usize = 64;
+
+/// Positions with at most this many edges left solve in well under a second,
+/// so players can lean on the solver for them during a game.
+pub const QUICK_SOLVE_EDGES: usize = 20;
+
+/// The result of solving a position with perfect play from both players.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    /// An optimal move for the player to move.
+    pub best_move: Move,
+    /// Boxes the player to move takes from the rest of the game minus the boxes
+    /// their opponent takes. Boxes already claimed are not counted.
+    pub margin: i32,
+    /// The number of positions searched.
+    pub nodes: u64,
+}
+
+/// Why a position cannot be solved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolveError {
+    /// The solver only handles two player games.
+    PlayerCount(u8),
+    /// The board has more edges than fit in the solver's board representation.
+    TooLarge { edges: usize },
+    /// Every edge has already been drawn.
+    GameOver,
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::PlayerCount(count) => {
+                write!(f, "only two player games can be solved, found {count}")
+            }
+            SolveError::TooLarge { edges } => write!(
+                f,
+                "the board has {edges} edges but at most {MAX_EDGES} can be solved"
+            ),
+            SolveError::GameOver => write!(f, "the game is already over"),
+        }
+    }
+}
+
+impl Error for SolveError {}
+
+/// Finds the best move and the margin it secures for the player to move,
+/// assuming both players play perfectly from here on.
+pub fn solve(state: &GameState) -> Result<Solution, SolveError> {
+    if state.player_count() != 2 {
+        return Err(SolveError::PlayerCount(state.player_count()));
+    }
+    if state.is_over() {
+        return Err(SolveError::GameOver);
+    }
+
+    let mut solver = Solver::new(state.height(), state.width())?;
+    let mask = solver
+        .edges
+        .iter()
+        .enumerate()
+        .filter(|(_, edge)| state.has_edge(**edge))
+        .fold(0, |mask, (index, _)| mask | 1 << index);
+
+    Ok(solver.solve_root(mask))
+}
+
+/// Maps a dot `(y, x)` on a board of `height` by `width` dots onto another dot.
+type Transform = fn(usize, usize, usize, usize) -> (usize, usize);
+
+/// What the transposition table knows about a position: bounds on its value
+/// and the best move found, as an edge of the position's canonical reflection.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    lower: i8,
+    upper: i8,
+    best: Option<u8>,
+}
+
+/// Zobrist hashes are already uniformly spread, so the table uses them as is.
+#[derive(Default)]
+struct ZobristHasher(u64);
+
+impl Hasher for ZobristHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ *byte as u64;
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = value;
+    }
+}
+
+/// Negamax search over positions packed into a `u64`, one bit per edge. The
+/// value of a position only depends on which edges are drawn, so boxes
+/// already claimed are left out and positions are shared between move orders.
+struct Solver {
+    edges: Vec<Move>,
+    full: u64,
+    /// The edge bits around each box.
+    box_masks: Vec<u64>,
+    /// The boxes on either side of each edge.
+    edge_boxes: Vec<Vec<usize>>,
+    /// Every symmetry of the board, starting with the identity, as a map from
+    /// edge index to the index of its image.
+    symmetries: Vec<Vec<usize>>,
+    /// The inverse of each symmetry.
+    inverses: Vec<Vec<usize>>,
+    /// Zobrist keys for each edge under each symmetry, so a position's hash
+    /// under a symmetry is the hash of its image.
+    zobrist: Vec<Vec<u64>>,
+    table: HashMap<u64, Entry, BuildHasherDefault<ZobristHasher>>,
+    nodes: u64,
+}
+
+impl Solver {
+    fn new(height: usize, width: usize) -> Result<Solver, SolveError> {
+        // Refuse big boards before listing their edges
+        let edge_count = height * (width - 1) + (height - 1) * width;
+        if edge_count > MAX_EDGES {
+            return Err(SolveError::TooLarge { edges: edge_count });
+        }
+
+        // Horizontal edges first, then vertical, as in GameState::legal_moves
+        let mut edges = Vec::with_capacity(edge_count);
+        for y in 0..height {
+            for x in 0..width - 1 {
+                edges.push(Move::new(y, x, Direction::Horizontal));
+            }
+        }
+        for y in 0..height - 1 {
+            for x in 0..width {
+                edges.push(Move::new(y, x, Direction::Vertical));
+            }
+        }
+        let index_of = |edge: Move| edges.iter().position(|e| *e == edge);
+
+        let scratch = GameState::new(height as u16, width as u16, 2);
+        let mut box_masks = vec![0; (height - 1) * (width - 1)];
+        let mut edge_boxes = Vec::with_capacity(edges.len());
+        for (index, edge) in edges.iter().enumerate() {
+            let boxes: Vec<usize> = scratch
+                .adjacent_boxes(*edge)
+                .into_iter()
+                .map(|(y, x)| y * (width - 1) + x)
+                .collect();
+            for b in &boxes {
+                box_masks[*b] |= 1 << index;
+            }
+            edge_boxes.push(boxes);
+        }
+
+        // Reflections of the dots, plus the diagonal ones on a square board
+        let mut transforms: Vec<Transform> = vec![
+            |y, x, _, _| (y, x),
+            |y, x, _, w| (y, w - 1 - x),
+            |y, x, h, _| (h - 1 - y, x),
+            |y, x, h, w| (h - 1 - y, w - 1 - x),
+        ];
+        if height == width {
+            transforms.extend([
+                (|y, x, _, _| (x, y)) as Transform,
+                |y, x, h, w| (w - 1 - x, h - 1 - y),
+                |y, x, _, w| (w - 1 - x, y),
+                |y, x, h, _| (x, h - 1 - y),
+            ]);
+        }
+        let symmetries: Vec<Vec<usize>> = transforms
+            .into_iter()
+            .map(|transform| {
+                edges
+                    .iter()
+                    .map(|edge| {
+                        let (end_y, end_x) = match edge.direction {
+                            Direction::Horizontal => (edge.y, edge.x + 1),
+                            Direction::Vertical => (edge.y + 1, edge.x),
+                        };
+                        let (y1, x1) = transform(edge.y, edge.x, height, width);
+                        let (y2, x2) = transform(end_y, end_x, height, width);
+                        let image = if y1 == y2 {
+                            Move::new(y1, x1.min(x2), Direction::Horizontal)
+                        } else {
+                            Move::new(y1.min(y2), x1, Direction::Vertical)
+                        };
+                        index_of(image).expect("symmetries map edges onto edges")
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // A fixed seed keeps the search identical from run to run
+        let mut rng = Rng::new(0x5EED);
+        let keys: Vec<u64> = edges.iter().map(|_| rng.next_u64()).collect();
+        let zobrist = symmetries
+            .iter()
+            .map(|symmetry| symmetry.iter().map(|image| keys[*image]).collect())
+            .collect();
+        let inverses = symmetries
+            .iter()
+            .map(|symmetry| {
+                let mut inverse = vec![0; symmetry.len()];
+                for (index, image) in symmetry.iter().enumerate() {
+                    inverse[*image] = index;
+                }
+                inverse
+            })
+            .collect();
+
+        let full = if edges.len() == 64 {
+            u64::MAX
+        } else {
+            (1 << edges.len()) - 1
+        };
+
+        Ok(Solver {
+            edges,
+            full,
+            box_masks,
+            edge_boxes,
+            symmetries,
+            inverses,
+            zobrist,
+            table: HashMap::default(),
+            nodes: 0,
+        })
+    }
+
+    /// The smallest Zobrist hash among the position and its reflections, so
+    /// that symmetric positions share a table entry, along with the symmetry
+    /// that gave it.
+    fn hash(&self, mask: u64) -> (u64, usize) {
+        let mut smallest = (u64::MAX, 0);
+        for (symmetry, keys) in self.zobrist.iter().enumerate() {
+            let mut hash = 0;
+            let mut bits = mask;
+            while bits != 0 {
+                hash ^= keys[bits.trailing_zeros() as usize];
+                bits &= bits - 1;
+            }
+            smallest = smallest.min((hash, symmetry));
+        }
+
+        smallest
+    }
+
+    fn sides(&self, mask: u64, b: usize) -> u32 {
+        (mask & self.box_masks[b]).count_ones()
+    }
+
+    /// The undrawn edges with the boxes each completes, captures first, then
+    /// moves that draw no third side, then the rest.
+    ///
+    /// A capture that cannot set up another box is never worse than any other
+    /// move, so when one exists it is the only move returned. Otherwise when a
+    /// box can be taken the only moves worth trying are the captures and the
+    /// double-dealing moves that hand the last two boxes of a chain away.
+    fn moves(&self, mask: u64) -> Vec<(usize, i32)> {
+        let mut captures = Vec::new();
+        let mut safe = Vec::new();
+        let mut rest = Vec::new();
+        for index in 0..self.edges.len() {
+            if mask & 1 << index != 0 {
+                continue;
+            }
+            let boxes = &self.edge_boxes[index];
+            let completed = boxes.iter().filter(|b| self.sides(mask, **b) == 3).count() as i32;
+            let third_side = boxes.iter().any(|b| self.sides(mask, *b) == 2);
+            if completed > 0 {
+                if !third_side {
+                    return vec![(index, completed)];
+                }
+                captures.push((index, completed));
+            } else if !third_side {
+                safe.push((index, 0));
+            } else {
+                rest.push((index, 0));
+            }
+        }
+
+        if !captures.is_empty() {
+            let mut declines = Vec::new();
+            for (index, _) in &captures {
+                for b in &self.edge_boxes[*index] {
+                    if self.sides(mask, *b) != 2 {
+                        continue;
+                    }
+                    let open = self.box_masks[*b] & !mask & !(1 << index);
+                    let decline = open.trailing_zeros() as usize;
+                    let taken = captures.iter().any(|(capture, _)| *capture == decline);
+                    if !taken && !declines.contains(&(decline, 0)) {
+                        declines.push((decline, 0));
+                    }
+                }
+            }
+            captures.extend(declines);
+            return captures;
+        }
+        captures.extend(safe);
+        captures.extend(rest);
+
+        captures
+    }
+
+    /// The value of a move: the boxes it completes plus the rest of the game
+    /// for whoever moves next.
+    fn play(&mut self, mask: u64, index: usize, completed: i32, alpha: i32, beta: i32) -> i32 {
+        let child = mask | 1 << index;
+        if completed > 0 {
+            // Completing a box keeps the turn
+            completed + self.negamax(child, alpha - completed, beta - completed)
+        } else {
+            -self.negamax(child, -beta, -alpha)
+        }
+    }
+
+    fn negamax(&mut self, mask: u64, mut alpha: i32, mut beta: i32) -> i32 {
+        self.nodes += 1;
+        if mask == self.full {
+            return 0;
+        }
+
+        let (key, symmetry) = self.hash(mask);
+        let entry = self.table.get(&key).copied();
+        if let Some(entry) = entry {
+            let (lower, upper) = (entry.lower as i32, entry.upper as i32);
+            if lower >= beta || lower == upper {
+                return lower;
+            }
+            if upper <= alpha {
+                return upper;
+            }
+            alpha = alpha.max(lower);
+            beta = beta.min(upper);
+        }
+        let original = (alpha, beta);
+
+        // Try the best move from an earlier search of this position first
+        let mut moves = self.moves(mask);
+        let earlier = entry
+            .and_then(|entry| entry.best)
+            .map(|best| self.inverses[symmetry][best as usize]);
+        if let Some(position) = moves.iter().position(|(index, _)| Some(*index) == earlier) {
+            moves[..=position].rotate_right(1);
+        }
+
+        let mut best = i32::MIN;
+        let mut best_index = moves[0].0;
+        for (index, completed) in moves {
+            let value = self.play(mask, index, completed, alpha, beta);
+            if value > best {
+                best = value;
+                best_index = index;
+            }
+            alpha = alpha.max(value);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        // Fail low gives an upper bound, fail high a lower bound
+        let boxes = self.box_masks.len() as i8;
+        let mut entry = entry.unwrap_or(Entry {
+            lower: -boxes,
+            upper: boxes,
+            best: None,
+        });
+        if best > original.0 {
+            entry.lower = entry.lower.max(best as i8);
+            entry.best = Some(self.symmetries[symmetry][best_index] as u8);
+        }
+        if best < original.1 {
+            entry.upper = entry.upper.min(best as i8);
+        }
+        self.table.insert(key, entry);
+
+        best
+    }
+
+    fn solve_root(&mut self, mask: u64) -> Solution {
+        // Narrow the margin down with null window searches, which prune far
+        // more than one search with a full window
+        let remaining = (0..self.box_masks.len())
+            .filter(|b| self.sides(mask, *b) < 4)
+            .count() as i32;
+        let (mut lower, mut upper) = (-remaining, remaining);
+        while lower < upper {
+            let guess = lower + (upper - lower + 1) / 2;
+            let value = self.negamax(mask, guess - 1, guess);
+            if value >= guess {
+                lower = value;
+            } else {
+                upper = value;
+            }
+        }
+
+        // Any move that reaches the margin is a best move
+        let moves = self.moves(mask);
+        let best_move = moves
+            .iter()
+            .find(|(index, completed)| {
+                self.play(mask, *index, *completed, lower - 1, lower) >= lower
+            })
+            .or(moves.first())
+            .map(|(index, _)| self.edges[*index])
+            .expect("an unfinished game has a legal move");
+
+        Solution {
+            best_move,
+            margin: lower,
+            nodes: self.nodes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// The margin for the player to move, found by trying every move with
+    /// plain game state and no pruning. Positions are keyed by the edges
+    /// left, which is all the margin depends on.
+    fn brute_force(state: &mut GameState, seen: &mut HashMap<Vec<Move>, i32>) -> i32 {
+        let moves = state.legal_moves();
+        if moves.is_empty() {
+            return 0;
+        }
+        if let Some(margin) = seen.get(&moves) {
+            return *margin;
+        }
+
+        let mut best = i32::MIN;
+        for player_move in &moves {
+            let outcome = state.apply_move(*player_move).unwrap();
+            let gained = outcome.completed.len() as i32;
+            let rest = brute_force(state, seen);
+            state.undo();
+            let margin = if outcome.next_player == outcome.player {
+                gained + rest
+            } else {
+                gained - rest
+            };
+            best = best.max(margin);
+        }
+        seen.insert(moves, best);
+
+        best
+    }
+
+    /// Checks the solver against brute force for the position, and that its
+    /// best move really keeps the margin it claims.
+    fn check(state: &mut GameState) {
+        let mut seen = HashMap::new();
+        let expected = brute_force(state, &mut seen);
+        let solution = solve(state).unwrap();
+        assert_eq!(solution.margin, expected);
+
+        let outcome = state.apply_move(solution.best_move).unwrap();
+        let rest = brute_force(state, &mut seen);
+        let kept = if outcome.next_player == outcome.player {
+            outcome.completed.len() as i32 + rest
+        } else {
+            outcome.completed.len() as i32 - rest
+        };
+        state.undo();
+        assert_eq!(kept, expected, "best move {:?}", solution.best_move);
+    }
+
+    #[test]
+    fn matches_brute_force_on_empty_boards() {
+        for (height, width) in [(2, 2), (2, 3), (3, 2), (2, 5), (3, 3)] {
+            check(&mut GameState::new(height, width, 2));
+        }
+    }
+
+    #[test]
+    fn matches_brute_force_part_way_through() {
+        let mut rng = Rng::new(7);
+        for game in 0..20 {
+            let mut state = GameState::new(3 + game % 2, 4 - game % 2, 2);
+            // Play some random moves first so brute force stays quick, but
+            // leave at least one edge
+            let played = 4 + rng.below(state.legal_moves().len() - 4);
+            for _ in 0..played {
+                let player_move = *rng.choose(&state.legal_moves()).unwrap();
+                state.apply_move(player_move).unwrap();
+            }
+            check(&mut state);
+        }
+    }
+
+    #[test]
+    fn rejects_positions_it_cannot_solve() {
+        assert_eq!(
+            solve(&GameState::new(3, 3, 3)),
+            Err(SolveError::PlayerCount(3))
+        );
+        assert_eq!(
+            solve(&GameState::new(6, 7, 2)),
+            Err(SolveError::TooLarge { edges: 71 })
+        );
+        assert_eq!(
+            solve(&GameState::new(999, 999, 2)),
+            Err(SolveError::TooLarge { edges: 1_994_004 })
+        );
+    }
+}
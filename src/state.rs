@@ -0,0 +1,844 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::configuration::{valid_board_dimension, valid_player_count, Config, Rules};
+use crate::scoreboard::Scoreboard;
+
+/// The orientation of an edge between two dots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Horizontal,
+    Vertical,
+}
+
+/// A single edge, addressed by the dot it starts from. Horizontal edges run
+/// right from dot `(y, x)` and vertical edges run down from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub y: usize,
+    pub x: usize,
+    pub direction: Direction,
+}
+
+/// What happened as a result of a legal move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveOutcome {
+    /// The player who made the move.
+    pub player: u8,
+    /// The `(y, x)` coordinates of every box the move completed.
+    pub completed: Vec<(usize, usize)>,
+    /// The player whose turn it is after the move.
+    pub next_player: u8,
+    /// Whether the move drew the last edge on the board.
+    pub game_over: bool,
+}
+
+/// A move that has been played, kept so it can be undone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveRecord {
+    /// The player who made the move.
+    pub player: u8,
+    pub player_move: Move,
+    /// The `(y, x)` coordinates of every box the move completed.
+    pub completed: Vec<(usize, usize)>,
+}
+
+/// An entry in a move log: who played which edge and how many boxes it
+/// completed. Written to saves as `player y x h|v boxes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoggedMove {
+    pub player: u8,
+    pub player_move: Move,
+    pub boxes: usize,
+}
+
+/// The reason a move was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IllegalMove {
+    /// The move text is not of the form `y x h|v`.
+    Malformed,
+    /// The edge lies outside the game board.
+    OutOfBounds,
+    /// The edge has already been drawn.
+    AlreadyDrawn,
+    /// Every edge has been drawn.
+    GameOver,
+}
+
+/// The rules engine for a game of boxes. It holds the board and whose turn it
+/// is, and knows nothing about how moves are read or the board is shown.
+///
+/// Players are numbered from 1. A cell owner of 0 marks an unclaimed box.
+#[derive(Debug, Clone)]
+pub struct GameState {
+    height: usize,
+    width: usize,
+    player_count: u8,
+    player_turn: u8,
+    horizontal: Vec<bool>,
+    vertical: Vec<bool>,
+    owners: Vec<u8>,
+    remaining_edges: usize,
+    rules: Rules,
+    hints_used: Vec<u32>,
+    history: Vec<MoveRecord>,
+    undone: Vec<Move>,
+}
+
+impl Move {
+    pub fn new(y: usize, x: usize, direction: Direction) -> Move {
+        Move { y, x, direction }
+    }
+}
+
+impl FromStr for Move {
+    type Err = IllegalMove;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(' ').collect();
+
+        // Ensure only 3 arguments are given
+        if parts.len() != 3 {
+            return Err(IllegalMove::Malformed);
+        }
+        // Parse input coordinates as usize. Automatically rejects negative input
+        let Ok(y) = parts[0].parse::<usize>() else {
+            return Err(IllegalMove::Malformed);
+        };
+        let Ok(x) = parts[1].parse::<usize>() else {
+            return Err(IllegalMove::Malformed);
+        };
+        let direction = match parts[2] {
+            "h" => Direction::Horizontal,
+            "v" => Direction::Vertical,
+            _ => return Err(IllegalMove::Malformed),
+        };
+
+        Ok(Move { y, x, direction })
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::Horizontal => 'h',
+            Direction::Vertical => 'v',
+        };
+        write!(f, "{} {} {direction}", self.y, self.x)
+    }
+}
+
+impl From<&MoveRecord> for LoggedMove {
+    fn from(record: &MoveRecord) -> Self {
+        LoggedMove {
+            player: record.player,
+            player_move: record.player_move,
+            boxes: record.completed.len(),
+        }
+    }
+}
+
+impl FromStr for LoggedMove {
+    type Err = IllegalMove;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(' ').collect();
+        if parts.len() != 5 {
+            return Err(IllegalMove::Malformed);
+        }
+        let Ok(player) = parts[0].parse::<u8>() else {
+            return Err(IllegalMove::Malformed);
+        };
+        let player_move = parts[1..4].join(" ").parse::<Move>()?;
+        let Ok(boxes) = parts[4].parse::<usize>() else {
+            return Err(IllegalMove::Malformed);
+        };
+
+        Ok(LoggedMove {
+            player,
+            player_move,
+            boxes,
+        })
+    }
+}
+
+impl fmt::Display for LoggedMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.player, self.player_move, self.boxes)
+    }
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IllegalMove::Malformed => write!(f, "moves are written as y x h|v"),
+            IllegalMove::OutOfBounds => write!(f, "edge is outside the game board"),
+            IllegalMove::AlreadyDrawn => write!(f, "edge has already been drawn"),
+            IllegalMove::GameOver => write!(f, "the game is over"),
+        }
+    }
+}
+
+impl Error for IllegalMove {}
+
+impl GameState {
+    /// Creates an empty board of `height` by `width` dots with player 1 to move.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either dimension is outside 2 to 999 dots or the player
+    /// count is outside 2 to 100, the same ranges a configuration accepts.
+    pub fn new(height: u16, width: u16, player_count: u8) -> GameState {
+        assert!(
+            valid_board_dimension(height) && valid_board_dimension(width),
+            "a board must be 2 to 999 dots on each side, not {height}x{width}"
+        );
+        assert!(
+            valid_player_count(player_count),
+            "a game must have 2 to 100 players, not {player_count}"
+        );
+        let height = height as usize;
+        let width = width as usize;
+        let horizontal = vec![false; height * (width - 1)];
+        let vertical = vec![false; (height - 1) * width];
+
+        GameState {
+            height,
+            width,
+            player_count,
+            player_turn: 1,
+            remaining_edges: horizontal.len() + vertical.len(),
+            horizontal,
+            vertical,
+            owners: vec![0; (height - 1) * (width - 1)],
+            rules: Rules::default(),
+            hints_used: vec![0; player_count as usize],
+            history: Vec::new(),
+            undone: Vec::new(),
+        }
+    }
+
+    /// Creates a game from a validated configuration, including any saved board.
+    pub fn build(config: &Config) -> GameState {
+        let mut state = GameState::new(config.height, config.width, config.player_count);
+        state.player_turn = config.player_turn;
+        state.rules = config.rules.clone();
+        if config.hints_used.len() == state.hints_used.len() {
+            state.hints_used = config.hints_used.clone();
+        }
+
+        // Edge rows alternate between horizontal and vertical, starting with horizontal
+        for (row, data) in config.board_edges.iter().enumerate() {
+            for (column, c) in data.chars().enumerate() {
+                if c != '1' {
+                    continue;
+                }
+                let direction = if row % 2 == 0 {
+                    Direction::Horizontal
+                } else {
+                    Direction::Vertical
+                };
+                if let Some(index) = state.edge_index(Move::new(row / 2, column, direction)) {
+                    state.set_edge(index, direction, true);
+                }
+            }
+        }
+
+        // Cells are comma separated player numbers
+        for (y, data) in config.board_cells.iter().enumerate() {
+            for (x, player) in data.split(',').enumerate() {
+                if let (Ok(player), true) = (player.trim().parse::<u8>(), state.is_box(y, x)) {
+                    state.owners[y * (state.width - 1) + x] = player;
+                }
+            }
+        }
+
+        // The loader has already checked the log against the board
+        let _ = state.restore_history(&config.move_log);
+
+        state
+    }
+
+    /// The number of dots down the board.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The number of dots across the board.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn player_count(&self) -> u8 {
+        self.player_count
+    }
+
+    pub fn current_player(&self) -> u8 {
+        self.player_turn
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// The hints each player has asked for, indexed from player 1.
+    pub fn hints_used(&self) -> &[u32] {
+        &self.hints_used
+    }
+
+    pub fn record_hint(&mut self, player: u8) {
+        if let Some(count) = self.hints_used.get_mut((player - 1) as usize) {
+            *count += 1;
+        }
+    }
+
+    /// Every move played so far, oldest first. A loaded game gets back the
+    /// moves in its save's move log, so only saves without one start with
+    /// an empty history.
+    pub fn history(&self) -> &[MoveRecord] {
+        &self.history
+    }
+
+    /// Whether the edge has been drawn. Edges outside the board are never drawn.
+    pub fn has_edge(&self, edge: Move) -> bool {
+        match self.edge_index(edge) {
+            Some(index) => match edge.direction {
+                Direction::Horizontal => self.horizontal[index],
+                Direction::Vertical => self.vertical[index],
+            },
+            None => false,
+        }
+    }
+
+    /// The player who claimed box `(y, x)`, or 0 if it is unclaimed.
+    pub fn owner(&self, y: usize, x: usize) -> u8 {
+        if self.is_box(y, x) {
+            self.owners[y * (self.width - 1) + x]
+        } else {
+            0
+        }
+    }
+
+    /// The number of drawn edges around box `(y, x)`.
+    pub fn sides(&self, y: usize, x: usize) -> u8 {
+        if !self.is_box(y, x) {
+            return 0;
+        }
+        [
+            Move::new(y, x, Direction::Horizontal),
+            Move::new(y + 1, x, Direction::Horizontal),
+            Move::new(y, x, Direction::Vertical),
+            Move::new(y, x + 1, Direction::Vertical),
+        ]
+        .into_iter()
+        .filter(|edge| self.has_edge(*edge))
+        .count() as u8
+    }
+
+    /// The number of boxes an undrawn edge would complete.
+    pub fn completes(&self, edge: Move) -> usize {
+        self.adjacent_boxes(edge)
+            .into_iter()
+            .filter(|(y, x)| self.sides(*y, *x) == 3)
+            .count()
+    }
+
+    /// Whether an undrawn edge would leave a box with three sides, letting
+    /// the next player claim it.
+    pub fn gives_third_side(&self, edge: Move) -> bool {
+        self.adjacent_boxes(edge)
+            .into_iter()
+            .any(|(y, x)| self.sides(y, x) == 2)
+    }
+
+    /// The undrawn edges of box `(y, x)`.
+    pub fn open_edges(&self, y: usize, x: usize) -> Vec<Move> {
+        [
+            Move::new(y, x, Direction::Horizontal),
+            Move::new(y + 1, x, Direction::Horizontal),
+            Move::new(y, x, Direction::Vertical),
+            Move::new(y, x + 1, Direction::Vertical),
+        ]
+        .into_iter()
+        .filter(|edge| self.is_box(y, x) && !self.has_edge(*edge))
+        .collect()
+    }
+
+    /// Checks a move against the board without applying it.
+    pub fn check_move(&self, player_move: Move) -> Result<(), IllegalMove> {
+        if self.is_over() {
+            return Err(IllegalMove::GameOver);
+        }
+        if self.edge_index(player_move).is_none() {
+            return Err(IllegalMove::OutOfBounds);
+        }
+        if self.has_edge(player_move) {
+            return Err(IllegalMove::AlreadyDrawn);
+        }
+
+        Ok(())
+    }
+
+    /// Draws an edge for the current player, claiming any boxes it completes.
+    /// The turn only passes on when no box was completed.
+    pub fn apply_move(&mut self, player_move: Move) -> Result<MoveOutcome, IllegalMove> {
+        let outcome = self.play(player_move)?;
+        self.undone.clear();
+
+        Ok(outcome)
+    }
+
+    /// Rebuilds the move history from a log of the moves that led to the
+    /// current board. The log is unwound from the current position, so each
+    /// entry must match an edge on the board, the boxes it completed and the
+    /// turn order. On a mismatch the index of the first bad entry is returned
+    /// and the game is left unchanged.
+    pub fn restore_history(&mut self, log: &[LoggedMove]) -> Result<(), usize> {
+        let mut unwound = self.clone();
+        let mut records = Vec::with_capacity(log.len());
+        for (index, entry) in log.iter().enumerate().rev() {
+            let edge = entry.player_move;
+            if entry.player == 0 || entry.player > self.player_count || !unwound.has_edge(edge) {
+                return Err(index);
+            }
+
+            // Before this edge was drawn none of its boxes could have been complete
+            let completed: Vec<(usize, usize)> = unwound
+                .adjacent_boxes(edge)
+                .into_iter()
+                .filter(|(y, x)| unwound.sides(*y, *x) == 4)
+                .collect();
+            if completed.len() != entry.boxes
+                || completed
+                    .iter()
+                    .any(|(y, x)| unwound.owner(*y, *x) != entry.player)
+            {
+                return Err(index);
+            }
+
+            let record = MoveRecord {
+                player: entry.player,
+                player_move: edge,
+                completed,
+            };
+            unwound.history.push(record.clone());
+            unwound.undo();
+            records.push(record);
+        }
+        records.reverse();
+
+        // Each move must have been made by the player whose turn it was
+        for (index, record) in records.iter().enumerate() {
+            let expected_turn = match records.get(index + 1) {
+                Some(next) => next.player,
+                None => self.player_turn,
+            };
+            let turn = if record.completed.is_empty() {
+                record.player % self.player_count + 1
+            } else {
+                record.player
+            };
+            if turn != expected_turn {
+                return Err(index);
+            }
+        }
+
+        self.history = records;
+        self.undone.clear();
+
+        Ok(())
+    }
+
+    /// Takes back the most recent move, releasing any boxes it completed and
+    /// handing the turn back to the player who made it.
+    ///
+    /// The engine always allows this; frontends decide whether to honour
+    /// [`Rules::allow_undo`].
+    pub fn undo(&mut self) -> Option<MoveRecord> {
+        let record = self.history.pop()?;
+        if let Some(index) = self.edge_index(record.player_move) {
+            self.set_edge(index, record.player_move.direction, false);
+        }
+        for (y, x) in &record.completed {
+            self.owners[y * (self.width - 1) + x] = 0;
+        }
+        self.player_turn = record.player;
+        self.undone.push(record.player_move);
+
+        Some(record)
+    }
+
+    /// Plays the most recently undone move again. Playing any other move
+    /// clears the moves available to redo.
+    pub fn redo(&mut self) -> Option<MoveOutcome> {
+        let player_move = self.undone.pop()?;
+
+        self.play(player_move).ok()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    fn play(&mut self, player_move: Move) -> Result<MoveOutcome, IllegalMove> {
+        self.check_move(player_move)?;
+        let Some(index) = self.edge_index(player_move) else {
+            return Err(IllegalMove::OutOfBounds);
+        };
+        self.set_edge(index, player_move.direction, true);
+
+        let player = self.player_turn;
+        let mut completed = Vec::new();
+        for (y, x) in self.adjacent_boxes(player_move) {
+            if self.sides(y, x) == 4 && self.owner(y, x) == 0 {
+                self.owners[y * (self.width - 1) + x] = player;
+                completed.push((y, x));
+            }
+        }
+
+        // Change player turn
+        if completed.is_empty() {
+            self.player_turn = self.player_turn % self.player_count + 1;
+        }
+        self.history.push(MoveRecord {
+            player,
+            player_move,
+            completed: completed.clone(),
+        });
+
+        Ok(MoveOutcome {
+            player,
+            completed,
+            next_player: self.player_turn,
+            game_over: self.is_over(),
+        })
+    }
+
+    /// Every edge that has not been drawn yet, horizontal edges first.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(self.remaining_edges);
+        for y in 0..self.height {
+            for x in 0..self.width - 1 {
+                if !self.horizontal[y * (self.width - 1) + x] {
+                    moves.push(Move::new(y, x, Direction::Horizontal));
+                }
+            }
+        }
+        for y in 0..self.height - 1 {
+            for x in 0..self.width {
+                if !self.vertical[y * self.width + x] {
+                    moves.push(Move::new(y, x, Direction::Vertical));
+                }
+            }
+        }
+
+        moves
+    }
+
+    /// The number of boxes claimed by each player, indexed from player 1.
+    pub fn scores(&self) -> Vec<u32> {
+        let mut scores = vec![0; self.player_count as usize];
+        for owner in &self.owners {
+            if *owner != 0 {
+                scores[(*owner - 1) as usize] += 1;
+            }
+        }
+
+        scores
+    }
+
+    /// The number of edges not drawn yet.
+    pub fn remaining_edges(&self) -> usize {
+        self.remaining_edges
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.remaining_edges == 0
+    }
+
+    /// Ranks the players by the boxes they have claimed so far.
+    pub fn scoreboard(&self) -> Scoreboard {
+        Scoreboard::build(&self.scores())
+    }
+
+    /// The players with the most boxes, in player order.
+    pub fn winners(&self) -> Vec<u8> {
+        self.scoreboard().winners()
+    }
+
+    fn is_box(&self, y: usize, x: usize) -> bool {
+        y < self.height - 1 && x < self.width - 1
+    }
+
+    fn edge_index(&self, edge: Move) -> Option<usize> {
+        match edge.direction {
+            Direction::Horizontal if edge.y < self.height && edge.x < self.width - 1 => {
+                Some(edge.y * (self.width - 1) + edge.x)
+            }
+            Direction::Vertical if edge.y < self.height - 1 && edge.x < self.width => {
+                Some(edge.y * self.width + edge.x)
+            }
+            _ => None,
+        }
+    }
+
+    fn set_edge(&mut self, index: usize, direction: Direction, drawn: bool) {
+        let edge = match direction {
+            Direction::Horizontal => &mut self.horizontal[index],
+            Direction::Vertical => &mut self.vertical[index],
+        };
+        if *edge != drawn {
+            *edge = drawn;
+            if drawn {
+                self.remaining_edges -= 1;
+            } else {
+                self.remaining_edges += 1;
+            }
+        }
+    }
+
+    /// The one or two boxes an edge borders, as `(y, x)`.
+    pub fn adjacent_boxes(&self, edge: Move) -> Vec<(usize, usize)> {
+        let mut boxes = Vec::with_capacity(2);
+        match edge.direction {
+            // Check above and below
+            Direction::Horizontal => {
+                if edge.y > 0 {
+                    boxes.push((edge.y - 1, edge.x));
+                }
+                if self.is_box(edge.y, edge.x) {
+                    boxes.push((edge.y, edge.x));
+                }
+            }
+            // Check left and right
+            Direction::Vertical => {
+                if edge.x > 0 {
+                    boxes.push((edge.y, edge.x - 1));
+                }
+                if self.is_box(edge.y, edge.x) {
+                    boxes.push((edge.y, edge.x));
+                }
+            }
+        }
+
+        boxes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn h(y: usize, x: usize) -> Move {
+        Move::new(y, x, Direction::Horizontal)
+    }
+
+    fn v(y: usize, x: usize) -> Move {
+        Move::new(y, x, Direction::Vertical)
+    }
+
+    fn play(state: &mut GameState, moves: &[Move]) {
+        for player_move in moves {
+            state.apply_move(*player_move).unwrap();
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_rejects_a_board_too_narrow_for_a_box() {
+        GameState::new(3, 1, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_rejects_a_game_without_players() {
+        GameState::new(3, 3, 0);
+    }
+
+    #[test]
+    fn a_move_without_a_box_passes_the_turn() {
+        let mut state = GameState::new(3, 3, 2);
+        let outcome = state.apply_move(h(0, 0)).unwrap();
+
+        assert_eq!(
+            outcome,
+            MoveOutcome {
+                player: 1,
+                completed: Vec::new(),
+                next_player: 2,
+                game_over: false,
+            }
+        );
+        assert!(state.has_edge(h(0, 0)));
+        assert_eq!(state.current_player(), 2);
+    }
+
+    #[test]
+    fn completing_a_box_claims_it_and_keeps_the_turn() {
+        let mut state = GameState::new(3, 3, 2);
+        play(&mut state, &[h(0, 0), h(1, 0), v(0, 0)]);
+        let outcome = state.apply_move(v(0, 1)).unwrap();
+
+        assert_eq!(outcome.player, 2);
+        assert_eq!(outcome.completed, vec![(0, 0)]);
+        assert_eq!(outcome.next_player, 2);
+        assert_eq!(state.owner(0, 0), 2);
+        assert_eq!(state.scores(), vec![0, 1]);
+    }
+
+    #[test]
+    fn one_edge_can_complete_two_boxes() {
+        let mut state = GameState::new(2, 3, 2);
+        play(&mut state, &[h(0, 0), h(0, 1), h(1, 0), h(1, 1), v(0, 0)]);
+        let outcome = state.apply_move(v(0, 2)).unwrap();
+        assert!(outcome.completed.is_empty());
+
+        let outcome = state.apply_move(v(0, 1)).unwrap();
+        assert_eq!(outcome.player, 1);
+        assert_eq!(outcome.completed, vec![(0, 0), (0, 1)]);
+        assert!(outcome.game_over);
+        assert_eq!(state.scores(), vec![2, 0]);
+    }
+
+    #[test]
+    fn rejects_malformed_moves() {
+        for text in ["", "0 0", "0 0 h v", "0 -1 h", "a 0 v", "0 0 d", "0  0 h"] {
+            assert_eq!(
+                text.parse::<Move>(),
+                Err(IllegalMove::Malformed),
+                "{text:?}"
+            );
+        }
+        assert_eq!("2 1 v".parse::<Move>(), Ok(v(2, 1)));
+    }
+
+    #[test]
+    fn rejects_edges_off_the_board() {
+        let mut state = GameState::new(3, 4, 2);
+        for edge in [h(0, 3), h(3, 0), v(2, 0), v(0, 4)] {
+            assert_eq!(state.apply_move(edge), Err(IllegalMove::OutOfBounds));
+        }
+        assert_eq!(state.remaining_edges(), 17);
+        assert_eq!(state.current_player(), 1);
+    }
+
+    #[test]
+    fn rejects_an_edge_drawn_twice() {
+        let mut state = GameState::new(3, 3, 2);
+        state.apply_move(v(1, 2)).unwrap();
+
+        assert_eq!(state.apply_move(v(1, 2)), Err(IllegalMove::AlreadyDrawn));
+        assert_eq!(state.current_player(), 2);
+    }
+
+    #[test]
+    fn rejects_moves_once_the_game_is_over() {
+        let mut state = GameState::new(2, 2, 2);
+        play(&mut state, &[h(0, 0), h(1, 0), v(0, 0)]);
+        assert!(!state.is_over());
+        state.apply_move(v(0, 1)).unwrap();
+
+        assert!(state.is_over());
+        assert_eq!(state.apply_move(h(0, 0)), Err(IllegalMove::GameOver));
+    }
+
+    #[test]
+    fn lists_undrawn_edges_horizontal_first() {
+        let mut state = GameState::new(2, 3, 2);
+        assert_eq!(
+            state.legal_moves(),
+            vec![
+                h(0, 0),
+                h(0, 1),
+                h(1, 0),
+                h(1, 1),
+                v(0, 0),
+                v(0, 1),
+                v(0, 2)
+            ]
+        );
+
+        play(&mut state, &[h(0, 1), v(0, 0)]);
+        assert_eq!(
+            state.legal_moves(),
+            vec![h(0, 0), h(1, 0), h(1, 1), v(0, 1), v(0, 2)]
+        );
+        assert_eq!(state.remaining_edges(), 5);
+    }
+
+    #[test]
+    fn winners_are_the_leaders_in_player_order() {
+        let mut state = GameState::new(3, 3, 3);
+        assert_eq!(state.winners(), vec![1, 2, 3]);
+
+        play(&mut state, &[h(0, 0), h(1, 0), v(0, 0), v(0, 1)]);
+        assert_eq!(state.owner(0, 0), 1);
+        assert_eq!(state.winners(), vec![1]);
+
+        play(&mut state, &[h(0, 1), h(2, 0), h(2, 1), v(1, 0), v(1, 1)]);
+        assert_eq!(state.owner(1, 0), 2);
+        assert_eq!(state.winners(), vec![1, 2]);
+    }
+
+    #[test]
+    fn undo_releases_both_boxes_of_a_double_capture() {
+        let mut state = GameState::new(2, 3, 2);
+        play(
+            &mut state,
+            &[h(0, 0), h(0, 1), h(1, 0), h(1, 1), v(0, 0), v(0, 2)],
+        );
+        state.apply_move(v(0, 1)).unwrap();
+        assert_eq!(state.scores(), vec![2, 0]);
+
+        let record = state.undo().unwrap();
+        assert_eq!(
+            record,
+            MoveRecord {
+                player: 1,
+                player_move: v(0, 1),
+                completed: vec![(0, 0), (0, 1)],
+            }
+        );
+        assert_eq!((state.owner(0, 0), state.owner(0, 1)), (0, 0));
+        assert_eq!(state.scores(), vec![0, 0]);
+        assert_eq!(state.current_player(), 1);
+        assert!(!state.has_edge(v(0, 1)));
+        assert!(!state.is_over());
+    }
+
+    #[test]
+    fn redo_replays_the_undone_move() {
+        let mut state = GameState::new(3, 3, 2);
+        play(&mut state, &[h(0, 0), h(1, 0), v(0, 0), v(0, 1)]);
+        state.undo();
+        state.undo();
+        assert_eq!(state.current_player(), 1);
+        assert!(state.can_redo());
+
+        let outcome = state.redo().unwrap();
+        assert_eq!(outcome.player, 1);
+        assert!(state.has_edge(v(0, 0)));
+        let outcome = state.redo().unwrap();
+        assert_eq!((outcome.player, outcome.completed), (2, vec![(0, 0)]));
+        assert_eq!(state.owner(0, 0), 2);
+        assert_eq!(state.redo(), None);
+    }
+
+    #[test]
+    fn a_new_move_clears_the_moves_to_redo() {
+        let mut state = GameState::new(3, 3, 2);
+        play(&mut state, &[h(0, 0), h(1, 0)]);
+        state.undo();
+        assert!(state.can_redo());
+
+        state.apply_move(v(1, 1)).unwrap();
+        assert!(!state.can_redo());
+        assert_eq!(state.redo(), None);
+        assert!(!state.has_edge(h(1, 0)));
+    }
+}
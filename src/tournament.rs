@@ -0,0 +1,565 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::bot::BotKind;
+use crate::configuration::valid_board_dimension;
+use crate::error::BoxesError;
+use crate::json::Json;
+use crate::rng::Rng;
+use crate::save::SaveFormat;
+use crate::state::GameState;
+
+/// Who plays whom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Every bot plays every other bot.
+    RoundRobin,
+    /// The first bot plays each of the others.
+    Gauntlet,
+}
+
+/// The settings for `boxes tournament`.
+#[derive(Debug, Clone)]
+pub struct Tournament {
+    pub bots: Vec<BotKind>,
+    pub mode: Mode,
+    /// Board sizes as height and width in dots, as for a new game.
+    pub sizes: Vec<(u16, u16)>,
+    /// Games per pairing on each board size. The bots take turns moving first.
+    pub games: usize,
+    pub seed: Option<u64>,
+    pub threads: Option<usize>,
+    /// Where to write every game's result, as JSON for a `.json` file and
+    /// CSV otherwise. The file must not already exist.
+    pub output: Option<String>,
+}
+
+/// One finished game between two entrants, numbered by their position in
+/// [`Tournament::bots`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameResult {
+    pub first: usize,
+    pub second: usize,
+    pub height: u16,
+    pub width: u16,
+    pub seed: u64,
+    pub first_score: u32,
+    pub second_score: u32,
+}
+
+/// How one entrant did over the whole tournament.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub bot: usize,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Boxes won minus boxes lost, summed over every game.
+    pub margin: i64,
+    pub elo: f64,
+}
+
+impl Record {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn average_margin(&self) -> f64 {
+        if self.games() == 0 {
+            0.0
+        } else {
+            self.margin as f64 / self.games() as f64
+        }
+    }
+}
+
+impl Tournament {
+    /// Builds the settings for `boxes tournament [options] bot bot...`.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Tournament, BoxesError> {
+        let mut tournament = Tournament {
+            bots: Vec::new(),
+            mode: Mode::RoundRobin,
+            sizes: Vec::new(),
+            games: 10,
+            seed: None,
+            threads: None,
+            output: None,
+        };
+        let mut args = args.skip(2);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--mode" => {
+                    tournament.mode = match args.next().as_deref() {
+                        Some("round-robin") => Mode::RoundRobin,
+                        Some("gauntlet") => Mode::Gauntlet,
+                        _ => return Err(BoxesError::Usage),
+                    }
+                }
+                "--size" => {
+                    let size = args.next().ok_or(BoxesError::Usage)?;
+                    tournament.sizes.push(parse_size(&size)?);
+                }
+                "--games" => {
+                    let games = args.next().and_then(|games| games.parse::<usize>().ok());
+                    tournament.games = games.filter(|games| *games > 0).ok_or(BoxesError::Usage)?;
+                }
+                "--seed" => {
+                    let seed = args.next().and_then(|seed| seed.parse::<u64>().ok());
+                    tournament.seed = Some(seed.ok_or(BoxesError::Usage)?);
+                }
+                "--threads" => {
+                    let threads = args
+                        .next()
+                        .and_then(|threads| threads.parse::<usize>().ok());
+                    let threads = threads.filter(|threads| *threads > 0);
+                    tournament.threads = Some(threads.ok_or(BoxesError::Usage)?);
+                }
+                "--output" => tournament.output = Some(args.next().ok_or(BoxesError::Usage)?),
+                _ => tournament
+                    .bots
+                    .push(arg.parse::<BotKind>().map_err(|_| BoxesError::Usage)?),
+            }
+        }
+
+        if tournament.bots.len() < 2 {
+            return Err(BoxesError::Usage);
+        }
+        if tournament.sizes.is_empty() {
+            tournament.sizes.push((5, 6));
+        }
+
+        Ok(tournament)
+    }
+
+    /// The pairs of entrants that meet, in a fixed order.
+    fn pairings(&self) -> Vec<(usize, usize)> {
+        let count = self.bots.len();
+        match self.mode {
+            Mode::RoundRobin => (0..count)
+                .flat_map(|a| (a + 1..count).map(move |b| (a, b)))
+                .collect(),
+            Mode::Gauntlet => (1..count).map(|b| (0, b)).collect(),
+        }
+    }
+
+    /// Every game to play as the first and second entrant, the board size
+    /// and the game's seed. The entrants of a pairing take turns moving
+    /// first, and every game gets its own seed so results do not depend on
+    /// scheduling.
+    fn schedule(&self, base_seed: u64) -> Vec<(usize, usize, u16, u16, u64)> {
+        let mut jobs = Vec::new();
+        for (first, second) in self.pairings() {
+            for (height, width) in &self.sizes {
+                for game in 0..self.games {
+                    let (first, second) = if game % 2 == 0 {
+                        (first, second)
+                    } else {
+                        (second, first)
+                    };
+                    let seed = Rng::new(base_seed ^ jobs.len() as u64).next_u64();
+                    jobs.push((first, second, *height, *width, seed));
+                }
+            }
+        }
+
+        jobs
+    }
+}
+
+/// A size written as `HxW`, such as `5x6`.
+fn parse_size(size: &str) -> Result<(u16, u16), BoxesError> {
+    let (height, width) = size.split_once('x').ok_or(BoxesError::Dimensions)?;
+    let (Ok(height), Ok(width)) = (height.parse::<u16>(), width.parse::<u16>()) else {
+        return Err(BoxesError::Dimensions);
+    };
+    if !valid_board_dimension(height) || !valid_board_dimension(width) {
+        return Err(BoxesError::Dimensions);
+    }
+
+    Ok((height, width))
+}
+
+/// Plays every game of the tournament across the available cores, writes
+/// the results file if one was asked for and returns the standings table.
+pub fn run(tournament: Tournament) -> Result<String, BoxesError> {
+    // Create the results file first, so a clash is found before any games
+    // are played rather than after
+    let mut output = match &tournament.output {
+        Some(path) => Some(
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .map_err(BoxesError::Output)?,
+        ),
+        None => None,
+    };
+
+    let base_seed = tournament
+        .seed
+        .unwrap_or_else(|| Rng::from_entropy().next_u64());
+
+    let jobs = tournament.schedule(base_seed);
+    let threads = tournament
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get))
+        .min(jobs.len());
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, GameResult)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut finished = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((first, second, height, width, seed)) = jobs.get(index) else {
+                            break;
+                        };
+                        let (first_score, second_score) = play_game(
+                            tournament.bots[*first],
+                            tournament.bots[*second],
+                            *height,
+                            *width,
+                            *seed,
+                        );
+                        finished.push((
+                            index,
+                            GameResult {
+                                first: *first,
+                                second: *second,
+                                height: *height,
+                                width: *width,
+                                seed: *seed,
+                                first_score,
+                                second_score,
+                            },
+                        ));
+                    }
+                    finished
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("tournament games do not panic"))
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    let results: Vec<GameResult> = results.into_iter().map(|(_, result)| result).collect();
+
+    let records = records(tournament.bots.len(), &results);
+    if let (Some(path), Some(file)) = (&tournament.output, &mut output) {
+        let contents = match SaveFormat::from_path(path) {
+            SaveFormat::Json => results_json(&tournament, &results, &records).to_string(),
+            SaveFormat::Text => results_csv(&tournament, &results),
+        };
+        file.write_all(contents.as_bytes())
+            .map_err(BoxesError::Output)?;
+    }
+
+    Ok(report(&tournament, &records, results.len()))
+}
+
+/// Plays one game without printing anything and returns both scores.
+fn play_game(first: BotKind, second: BotKind, height: u16, width: u16, seed: u64) -> (u32, u32) {
+    let mut seeder = Rng::new(seed);
+    let mut bots = [
+        first.build(Rng::new(seeder.next_u64())),
+        second.build(Rng::new(seeder.next_u64())),
+    ];
+    let mut state = GameState::new(height, width, 2);
+    while !state.is_over() {
+        let bot = &mut bots[(state.current_player() - 1) as usize];
+        let player_move = bot.choose_move(&state);
+        state
+            .apply_move(player_move)
+            .expect("bots only choose legal moves");
+    }
+    let scores = state.scores();
+
+    (scores[0], scores[1])
+}
+
+/// Tallies each entrant's results and fits Elo ratings to them.
+pub fn records(bot_count: usize, results: &[GameResult]) -> Vec<Record> {
+    let mut records: Vec<Record> = (0..bot_count)
+        .map(|bot| Record {
+            bot,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+            margin: 0,
+            elo: 1500.0,
+        })
+        .collect();
+    for result in results {
+        let margin = result.first_score as i64 - result.second_score as i64;
+        for (bot, margin) in [(result.first, margin), (result.second, -margin)] {
+            let record = &mut records[bot];
+            record.margin += margin;
+            match margin {
+                m if m > 0 => record.wins += 1,
+                0 => record.draws += 1,
+                _ => record.losses += 1,
+            }
+        }
+    }
+
+    let ratings = fit_elo(bot_count, results);
+    for (record, elo) in records.iter_mut().zip(ratings) {
+        record.elo = elo;
+    }
+
+    records
+}
+
+/// Ratings that best explain the results, found by repeatedly nudging each
+/// rating towards its expected score. Ratings average 1500, and are kept
+/// within 1000 of that for entrants that win or lose every game.
+fn fit_elo(bot_count: usize, results: &[GameResult]) -> Vec<f64> {
+    let expected = |a: f64, b: f64| 1.0 / (1.0 + 10f64.powf((b - a) / 400.0));
+    let mut ratings = vec![1500.0; bot_count];
+    for _ in 0..500 {
+        let mut surplus = vec![0.0; bot_count];
+        let mut games = vec![0u32; bot_count];
+        for result in results {
+            let score = match result.first_score.cmp(&result.second_score) {
+                std::cmp::Ordering::Greater => 1.0,
+                std::cmp::Ordering::Equal => 0.5,
+                std::cmp::Ordering::Less => 0.0,
+            };
+            let expectation = expected(ratings[result.first], ratings[result.second]);
+            surplus[result.first] += score - expectation;
+            surplus[result.second] -= score - expectation;
+            games[result.first] += 1;
+            games[result.second] += 1;
+        }
+        for bot in 0..bot_count {
+            if games[bot] > 0 {
+                ratings[bot] += 100.0 * surplus[bot] / games[bot] as f64;
+            }
+        }
+        let mean = ratings.iter().sum::<f64>() / bot_count as f64;
+        for rating in ratings.iter_mut() {
+            *rating = (*rating - mean + 1500.0).clamp(500.0, 2500.0);
+        }
+    }
+
+    ratings
+}
+
+fn report(tournament: &Tournament, records: &[Record], game_count: usize) -> String {
+    let mode = match tournament.mode {
+        Mode::RoundRobin => "round-robin",
+        Mode::Gauntlet => "gauntlet",
+    };
+    let names: Vec<String> = tournament.bots.iter().map(BotKind::to_string).collect();
+    let name_width = names.iter().map(String::len).max().unwrap_or(0).max(3);
+
+    let mut ranked: Vec<&Record> = records.iter().collect();
+    ranked.sort_by(|a, b| b.elo.total_cmp(&a.elo).then(a.bot.cmp(&b.bot)));
+
+    let mut lines = vec![format!(
+        "Tournament: {mode}, {} bots, {game_count} game(s)",
+        tournament.bots.len()
+    )];
+    lines.push(format!(
+        "{:>3}. {:<name_width$}  {:>5} {:>5} {:>5}  {:>10}  {:>5}",
+        "#", "Bot", "W", "D", "L", "Avg margin", "Elo"
+    ));
+    for (rank, record) in ranked.iter().enumerate() {
+        lines.push(format!(
+            "{:>3}. {:<name_width$}  {:>5} {:>5} {:>5}  {:>+10.2}  {:>5.0}",
+            rank + 1,
+            names[record.bot],
+            record.wins,
+            record.draws,
+            record.losses,
+            record.average_margin(),
+            record.elo
+        ));
+    }
+
+    lines.join("\n")
+}
+
+fn results_csv(tournament: &Tournament, results: &[GameResult]) -> String {
+    let mut csv = String::from("game,height,width,first,second,first_score,second_score,seed\n");
+    for (index, result) in results.iter().enumerate() {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{}\n",
+            index + 1,
+            result.height,
+            result.width,
+            tournament.bots[result.first],
+            tournament.bots[result.second],
+            result.first_score,
+            result.second_score,
+            result.seed
+        ));
+    }
+
+    csv
+}
+
+fn results_json(tournament: &Tournament, results: &[GameResult], records: &[Record]) -> Json {
+    let games = results
+        .iter()
+        .map(|result| {
+            Json::object(vec![
+                ("height", result.height.into()),
+                ("width", result.width.into()),
+                ("first", tournament.bots[result.first].to_string().into()),
+                ("second", tournament.bots[result.second].to_string().into()),
+                ("first_score", result.first_score.into()),
+                ("second_score", result.second_score.into()),
+                // Seeds can exceed what a JSON number holds exactly
+                ("seed", result.seed.to_string().into()),
+            ])
+        })
+        .collect();
+    let standings = records
+        .iter()
+        .map(|record| {
+            Json::object(vec![
+                ("bot", tournament.bots[record.bot].to_string().into()),
+                ("wins", record.wins.into()),
+                ("draws", record.draws.into()),
+                ("losses", record.losses.into()),
+                ("average_margin", record.average_margin().into()),
+                ("elo", record.elo.round().into()),
+            ])
+        })
+        .collect();
+
+    Json::object(vec![
+        ("games", Json::Array(games)),
+        ("standings", Json::Array(standings)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tournament(bot_count: usize, mode: Mode) -> Tournament {
+        Tournament {
+            bots: vec![BotKind::Random; bot_count],
+            mode,
+            sizes: vec![(3, 3), (4, 5)],
+            games: 3,
+            seed: None,
+            threads: None,
+            output: None,
+        }
+    }
+
+    fn result(first: usize, second: usize, first_score: u32, second_score: u32) -> GameResult {
+        GameResult {
+            first,
+            second,
+            height: 3,
+            width: 3,
+            seed: 0,
+            first_score,
+            second_score,
+        }
+    }
+
+    #[test]
+    fn round_robin_pairs_every_bot_once() {
+        assert_eq!(
+            tournament(4, Mode::RoundRobin).pairings(),
+            vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]
+        );
+    }
+
+    #[test]
+    fn gauntlet_pairs_the_first_bot_with_each_other() {
+        assert_eq!(
+            tournament(4, Mode::Gauntlet).pairings(),
+            vec![(0, 1), (0, 2), (0, 3)]
+        );
+    }
+
+    #[test]
+    fn entrants_take_turns_moving_first() {
+        let jobs = tournament(2, Mode::RoundRobin).schedule(7);
+        let games: Vec<(usize, usize, u16, u16)> = jobs
+            .iter()
+            .map(|(first, second, height, width, _)| (*first, *second, *height, *width))
+            .collect();
+
+        assert_eq!(
+            games,
+            vec![
+                (0, 1, 3, 3),
+                (1, 0, 3, 3),
+                (0, 1, 3, 3),
+                (0, 1, 4, 5),
+                (1, 0, 4, 5),
+                (0, 1, 4, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_game_gets_its_own_seed() {
+        let tournament = tournament(3, Mode::RoundRobin);
+        let seeds: Vec<u64> = tournament.schedule(7).iter().map(|job| job.4).collect();
+
+        assert_eq!(seeds.len(), 18);
+        assert_eq!(seeds[0], Rng::new(7).next_u64());
+        assert_eq!(seeds[5], Rng::new(7 ^ 5).next_u64());
+        let mut distinct = seeds.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct.len(), seeds.len());
+        assert_ne!(tournament.schedule(8)[0].4, seeds[0]);
+    }
+
+    #[test]
+    fn records_tally_results_for_both_entrants() {
+        let records = records(
+            3,
+            &[result(0, 1, 3, 1), result(1, 0, 2, 2), result(2, 0, 4, 0)],
+        );
+
+        let tallies: Vec<(u32, u32, u32, i64)> = records
+            .iter()
+            .map(|record| (record.wins, record.draws, record.losses, record.margin))
+            .collect();
+        assert_eq!(tallies, vec![(1, 1, 1, -2), (0, 1, 1, -2), (1, 0, 0, 4)]);
+    }
+
+    #[test]
+    fn elo_ranks_the_stronger_bot_higher() {
+        let results = [
+            result(0, 1, 5, 4),
+            result(1, 0, 3, 6),
+            result(0, 1, 4, 5),
+            result(1, 2, 8, 1),
+            result(2, 1, 2, 7),
+        ];
+        let ratings = fit_elo(3, &results);
+
+        assert!(ratings[0] > ratings[1] && ratings[1] > ratings[2]);
+        let mean = ratings.iter().sum::<f64>() / 3.0;
+        assert!((mean - 1500.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn elo_keeps_even_results_at_the_average() {
+        let ratings = fit_elo(2, &[result(0, 1, 5, 4), result(1, 0, 5, 4)]);
+        assert!(ratings.iter().all(|rating| (rating - 1500.0).abs() < 1e-6));
+
+        // An entrant that wins every game stays within 1000 of the average
+        let ratings = fit_elo(2, &vec![result(0, 1, 9, 0); 4]);
+        assert!(ratings[0] > ratings[1]);
+        assert!(ratings[0] <= 2500.0 && ratings[1] >= 500.0);
+    }
+}
@@ -0,0 +1,526 @@
+use std::io::{self, stdin, stdout, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::BoxesError;
+use crate::game::Game;
+use crate::state::{Direction, Move};
+use crate::width;
+
+/// The columns taken by the side panel, including the gap before it.
+const PANEL_WIDTH: usize = 24;
+
+/// The terminal size, in rows and columns, when `stty` cannot report one.
+const DEFAULT_SIZE: (usize, usize) = (24, 80);
+
+/// How close the cursor may come to the edge of the view before it scrolls.
+const SCROLL_MARGIN: usize = 2;
+
+/// How long to wait for the rest of an escape sequence before taking Esc as
+/// a key of its own.
+const ESCAPE_WAIT: Duration = Duration::from_millis(50);
+
+/// How long the keyboard must be idle before the terminal size is checked
+/// again. Std has no way to hear about a resize, so it is looked for while
+/// nothing else is happening.
+const RESIZE_CHECK: Duration = Duration::from_millis(500);
+
+/// The terminal in raw mode on the alternate screen. Dropping it puts the
+/// terminal back the way it was found, even if the game ends with an error.
+struct Terminal {
+    /// The settings from `stty -g` to restore.
+    saved: String,
+    /// The rows and columns of the terminal when it was last checked.
+    size: (usize, usize),
+}
+
+impl Terminal {
+    fn enter() -> Result<Terminal, BoxesError> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["raw", "-echo"])?;
+        let terminal = Terminal {
+            saved,
+            size: read_size(),
+        };
+        print!("\x1b[?1049h\x1b[?25l");
+        stdout().flush().map_err(BoxesError::System)?;
+
+        Ok(terminal)
+    }
+
+    /// Checks the size of the terminal again and says whether it changed.
+    fn resized(&mut self) -> bool {
+        let size = read_size();
+        let changed = size != self.size;
+        self.size = size;
+
+        changed
+    }
+}
+
+/// The rows and columns of the terminal, as `stty` reports them.
+fn read_size() -> (usize, usize) {
+    let size = stty(&["size"]).ok().and_then(|size| {
+        let (rows, columns) = size.trim().split_once(' ')?;
+        Some((rows.parse().ok()?, columns.parse().ok()?))
+    });
+
+    match size {
+        Some((rows, columns)) if rows > 0 && columns > 0 => (rows, columns),
+        _ => DEFAULT_SIZE,
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Runs `stty` on the terminal behind stdin and returns what it printed.
+fn stty(args: &[&str]) -> Result<String, BoxesError> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(BoxesError::System)?;
+    if !output.status.success() {
+        return Err(BoxesError::System(io::Error::other(
+            "the full-screen interface needs stdin to be a terminal",
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Quit,
+    Char(char),
+}
+
+/// Bytes typed at the terminal, read on their own thread so that waiting
+/// for one can time out.
+struct Input {
+    bytes: Receiver<u8>,
+}
+
+impl Input {
+    fn start() -> Input {
+        let (sender, bytes) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in stdin().lock().bytes() {
+                let Ok(byte) = byte else {
+                    break;
+                };
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Input { bytes }
+    }
+
+    /// The next byte, waiting for at most `wait` if given. `None` when the
+    /// wait runs out.
+    fn next(&self, wait: Option<Duration>) -> Result<Option<u8>, BoxesError> {
+        let byte = match wait {
+            Some(wait) => self.bytes.recv_timeout(wait),
+            None => self
+                .bytes
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match byte {
+            Ok(byte) => Ok(Some(byte)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(BoxesError::EndOfInput),
+        }
+    }
+}
+
+/// The next key, or `None` if none is pressed within `wait`.
+fn read_key(input: &Input, wait: Option<Duration>) -> Result<Option<Key>, BoxesError> {
+    let Some(byte) = input.next(wait)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(match byte {
+        // Arrow keys arrive as ESC [ A to ESC [ D, while Esc on its own
+        // arrives alone
+        0x1b => match input.next(Some(ESCAPE_WAIT))? {
+            Some(b'[') => match input.next(Some(ESCAPE_WAIT))? {
+                Some(b'A') => Key::Up,
+                Some(b'B') => Key::Down,
+                Some(b'C') => Key::Right,
+                Some(b'D') => Key::Left,
+                _ => Key::Char('\x1b'),
+            },
+            _ => Key::Char('\x1b'),
+        },
+        b'k' => Key::Up,
+        b'j' => Key::Down,
+        b'l' => Key::Right,
+        b'h' => Key::Left,
+        b'\r' | b'\n' => Key::Enter,
+        // Raw mode turns Ctrl-C into a plain byte
+        b'q' | 0x03 => Key::Quit,
+        byte => Key::Char(byte as char),
+    }))
+}
+
+/// A point on the lattice of dots, edges and boxes the board is drawn on:
+/// row `2y` and column `2x` is dot `(y, x)`. The cursor always rests on an
+/// edge, where exactly one of the row and column is odd.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cursor {
+    row: usize,
+    column: usize,
+}
+
+impl Cursor {
+    fn edge(self) -> Move {
+        let direction = if self.row.is_multiple_of(2) {
+            Direction::Horizontal
+        } else {
+            Direction::Vertical
+        };
+
+        Move::new(self.row / 2, self.column / 2, direction)
+    }
+
+    /// Moves one step on a lattice of `rows` by `columns`. A step that lands
+    /// between edges slides onto the neighbouring edge, so walking right
+    /// zigzags along the tops and sides of a row of boxes and every edge can
+    /// be reached.
+    fn step(&mut self, key: &Key, rows: usize, columns: usize) {
+        let (row, column) = (self.row, self.column);
+        match key {
+            Key::Left | Key::Right => {
+                let column = match key {
+                    Key::Left if column > 0 => column - 1,
+                    Key::Right if column + 1 < columns => column + 1,
+                    _ => return,
+                };
+                self.column = column;
+                if (row + column) % 2 == 0 {
+                    self.row = if row % 2 == 1 || row + 1 >= rows {
+                        row - 1
+                    } else {
+                        row + 1
+                    };
+                }
+            }
+            Key::Up | Key::Down => {
+                let row = match key {
+                    Key::Up if row > 0 => row - 1,
+                    Key::Down if row + 1 < rows => row + 1,
+                    _ => return,
+                };
+                self.row = row;
+                if (row + column) % 2 == 0 {
+                    self.column = if column % 2 == 1 || column + 1 >= columns {
+                        column - 1
+                    } else {
+                        column + 1
+                    };
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// What is on screen besides the board itself.
+struct View {
+    cursor: Cursor,
+    /// The first board line and column shown, for boards bigger than the
+    /// terminal.
+    top: usize,
+    left: usize,
+    status: String,
+}
+
+impl View {
+    fn draw(&mut self, game: &Game, terminal: &Terminal) -> Result<(), BoxesError> {
+        let (rows, columns) = terminal.size;
+        // The panel is dropped on terminals too narrow to share
+        let show_panel = columns > PANEL_WIDTH * 2;
+        let board_width = if show_panel {
+            columns - PANEL_WIDTH
+        } else {
+            columns
+        };
+        let board_height = rows.saturating_sub(1).max(1);
+
+        // Where the cursor's edge is drawn in the board text
+        let cell_width = game.cell_width();
+        let cursor_line = self.cursor.row;
+        let cursor_start = self.cursor.column / 2 * (cell_width + 1) + self.cursor.column % 2;
+        let cursor_width = if self.cursor.column % 2 == 1 {
+            cell_width
+        } else {
+            1
+        };
+        self.scroll(
+            (cursor_line, cursor_start, cursor_width),
+            board_height,
+            board_width,
+        );
+
+        let board = game.grid_lines();
+        let panel = if show_panel {
+            self.panel(game)
+        } else {
+            Vec::new()
+        };
+        let mut frame = String::from("\x1b[H");
+        for line in 0..board_height {
+            let text = board
+                .get(self.top + line)
+                .map(|text| columns_of(text))
+                .unwrap_or_default();
+            let last_column = self.left + board_width - 1;
+            for column in self.left..self.left + board_width {
+                let in_cursor = self.top + line == cursor_line
+                    && (cursor_start..cursor_start + cursor_width).contains(&column);
+                if in_cursor && column == cursor_start {
+                    frame.push_str("\x1b[7m");
+                }
+                // Show the edge the cursor would draw
+                let glyph = match text.get(column) {
+                    Some(Some(glyph)) if in_cursor && glyph == " " => self.cursor_glyph(),
+                    None if in_cursor => self.cursor_glyph(),
+                    // Wide symbols cut in half by the side of the view are left out
+                    Some(Some(glyph))
+                        if column == last_column && width::display_width(glyph) == 2 =>
+                    {
+                        " "
+                    }
+                    Some(None) if column == self.left => " ",
+                    Some(None) => continue,
+                    Some(Some(glyph)) => glyph,
+                    None => " ",
+                };
+                frame.push_str(glyph);
+                if in_cursor && column + 1 == cursor_start + cursor_width {
+                    frame.push_str("\x1b[0m");
+                }
+            }
+            frame.push_str("\x1b[0m");
+            if let Some(text) = panel.get(line) {
+                frame.push_str("  ");
+                frame.extend(text.chars().take(PANEL_WIDTH - 2));
+            }
+            frame.push_str("\x1b[K\r\n");
+        }
+        frame.extend(self.status.chars().take(columns));
+        frame.push_str("\x1b[K");
+
+        let mut out = stdout();
+        out.write_all(frame.as_bytes())
+            .and_then(|_| out.flush())
+            .map_err(BoxesError::System)
+    }
+
+    /// The edge the cursor would draw, shown where it is not drawn yet.
+    fn cursor_glyph(&self) -> &'static str {
+        match self.cursor.edge().direction {
+            Direction::Horizontal => "-",
+            Direction::Vertical => "|",
+        }
+    }
+
+    /// Moves the view just enough to keep the cursor's edge, given as its
+    /// line, first column and width, away from the sides.
+    fn scroll(&mut self, cursor: (usize, usize, usize), height: usize, width: usize) {
+        let (line, start, length) = cursor;
+        let margin = SCROLL_MARGIN.min(height.saturating_sub(1) / 2);
+        if line < self.top + margin {
+            self.top = line.saturating_sub(margin);
+        } else if line + margin >= self.top + height {
+            self.top = line + margin + 1 - height;
+        }
+
+        let margin = SCROLL_MARGIN.min(width.saturating_sub(length) / 2);
+        if start < self.left + margin {
+            self.left = start.saturating_sub(margin);
+        } else if start + length + margin > self.left + width {
+            self.left = start + length + margin - width;
+        }
+    }
+
+    fn panel(&self, game: &Game) -> Vec<String> {
+        let state = &game.state;
+        let mut lines = vec![
+            format!("{} by {} dots", state.height(), state.width()),
+            String::new(),
+        ];
+        if game.is_finished() {
+            lines.push("Game over".to_string());
+        } else {
+            let player = state.current_player();
+            lines.push(format!("Turn: {}", game.player_label(player)));
+        }
+        lines.push(String::new());
+
+        lines.push("Scores".to_string());
+        let label_width = (1..=state.player_count())
+            .map(|player| width::display_width(&game.player_label(player)))
+            .max()
+            .unwrap_or(1);
+        for (index, score) in state.scores().iter().enumerate() {
+            let player = index as u8 + 1;
+            let marker = if player == state.current_player() && !game.is_finished() {
+                '>'
+            } else {
+                ' '
+            };
+            lines.push(format!(
+                "{marker} {}  {score}",
+                width::pad(&game.player_label(player), label_width)
+            ));
+        }
+        lines.push(String::new());
+
+        lines.push(format!("Edge: {}", self.cursor.edge()));
+        lines.push(String::new());
+        lines.push("arrows/hjkl  move".to_string());
+        lines.push("Enter        draw edge".to_string());
+        lines.push("u / r        undo/redo".to_string());
+        lines.push("?            hint".to_string());
+        lines.push("q            quit".to_string());
+
+        lines
+    }
+}
+
+/// Splits a line of the board into screen columns. A wide glyph fills its
+/// first column and leaves `None` in the second, and marks, joiners and
+/// joined characters stay with the glyph they belong to.
+fn columns_of(text: &str) -> Vec<Option<String>> {
+    let mut columns: Vec<Option<String>> = Vec::new();
+    let mut joined = false;
+    for character in text.chars() {
+        let width = width::char_width(character);
+        if joined || width == 0 {
+            if let Some(glyph) = columns.iter_mut().rev().find_map(Option::as_mut) {
+                glyph.push(character);
+            }
+            joined = character == '\u{200d}';
+            continue;
+        }
+        columns.push(Some(character.to_string()));
+        if width == 2 {
+            columns.push(None);
+        }
+    }
+
+    columns
+}
+
+/// Plays a game full-screen. Humans steer a cursor over the edges and draw
+/// the one under it with Enter, while bots and engines move as in the line
+/// mode. Returns the final standings once the game ends, or the scores so
+/// far if the player quits.
+pub(crate) fn run(mut game: Game) -> Result<String, BoxesError> {
+    let mut terminal = Terminal::enter()?;
+    let input = Input::start();
+    let rows = game.state.height() * 2 - 1;
+    let columns = game.state.width() * 2 - 1;
+    let mut view = View {
+        cursor: Cursor { row: 0, column: 1 },
+        top: 0,
+        left: 0,
+        status: String::new(),
+    };
+
+    while !game.is_finished() {
+        view.draw(&game, &terminal)?;
+        let player = game.state.current_player();
+        if let Some(player_move) = game.computer_turn() {
+            view.status = format!("{} played {player_move}", game.player_label(player));
+            continue;
+        }
+        if let Some((player, cause)) = &game.forfeit {
+            view.status = format!("{} forfeits: {cause}", game.player_label(*player));
+            break;
+        }
+
+        let key = loop {
+            match read_key(&input, Some(RESIZE_CHECK))? {
+                Some(key) => break key,
+                None if terminal.resized() => view.draw(&game, &terminal)?,
+                None => (),
+            }
+        };
+        view.status.clear();
+        match key {
+            Key::Up | Key::Down | Key::Left | Key::Right => {
+                view.cursor.step(&key, rows, columns);
+            }
+            Key::Enter => match game.state.apply_move(view.cursor.edge()) {
+                Ok(outcome) if !outcome.completed.is_empty() => {
+                    view.status = format!(
+                        "{} completed {} box(es)",
+                        game.player_label(outcome.player),
+                        outcome.completed.len()
+                    );
+                }
+                Ok(_) => (),
+                Err(e) => view.status = e.to_string(),
+            },
+            Key::Char('u') => {
+                if let Err(e) = game.undo() {
+                    view.status = e.to_string();
+                }
+            }
+            Key::Char('r') => {
+                if let Err(e) = game.redo() {
+                    view.status = e.to_string();
+                }
+            }
+            Key::Char('?') => match game.hint() {
+                Ok(hint) => {
+                    // Put the cursor on the suggestion so Enter plays it
+                    let edge = hint.player_move;
+                    view.cursor = match edge.direction {
+                        Direction::Horizontal => Cursor {
+                            row: edge.y * 2,
+                            column: edge.x * 2 + 1,
+                        },
+                        Direction::Vertical => Cursor {
+                            row: edge.y * 2 + 1,
+                            column: edge.x * 2,
+                        },
+                    };
+                    view.status = format!("Hint: {hint}");
+                }
+                Err(e) => view.status = e.to_string(),
+            },
+            Key::Quit => return Ok(game.standings()),
+            Key::Char(_) => (),
+        }
+    }
+
+    // Leave the final board up until a key is pressed
+    if view.status.is_empty() {
+        view.status = "Game over, press any key".to_string();
+    } else {
+        view.status.push_str(". Game over, press any key");
+    }
+    view.draw(&game, &terminal)?;
+    let _ = read_key(&input, None);
+    drop(terminal);
+
+    Ok(game.standings())
+}
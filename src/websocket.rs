@@ -0,0 +1,314 @@
+use std::io::{self, Read, Write};
+use std::mem;
+
+/// Appended to a client's key before hashing, as fixed by RFC 6455.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The largest message accepted from a client.
+const MAX_MESSAGE: usize = 64 * 1024;
+
+/// A message read from a client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    /// Binary data, which the game API does not use.
+    Binary(Vec<u8>),
+    /// A ping, which must be answered with a pong carrying the same data.
+    Ping(Vec<u8>),
+    /// The client is closing the connection.
+    Close,
+}
+
+/// The value of `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{HANDSHAKE_GUID}", key.trim()).as_bytes()))
+}
+
+/// Reads messages from a client. A message sent in fragments is kept here
+/// while a control frame that arrives between them is returned.
+pub struct MessageReader<R> {
+    reader: R,
+    message: Vec<u8>,
+    opcode: Option<u8>,
+}
+
+impl<R: Read> MessageReader<R> {
+    pub fn new(reader: R) -> MessageReader<R> {
+        MessageReader {
+            reader,
+            message: Vec::new(),
+            opcode: None,
+        }
+    }
+
+    /// Reads the next message, joining fragments and skipping pongs. Client
+    /// frames are always masked.
+    pub fn read_message(&mut self) -> io::Result<Message> {
+        loop {
+            let mut header = [0; 2];
+            self.reader.read_exact(&mut header)?;
+            let finished = header[0] & 0x80 != 0;
+            let opcode = header[0] & 0x0f;
+            let masked = header[1] & 0x80 != 0;
+            let length = match header[1] & 0x7f {
+                126 => {
+                    let mut length = [0; 2];
+                    self.reader.read_exact(&mut length)?;
+                    u16::from_be_bytes(length) as usize
+                }
+                127 => {
+                    let mut length = [0; 8];
+                    self.reader.read_exact(&mut length)?;
+                    usize::try_from(u64::from_be_bytes(length)).unwrap_or(usize::MAX)
+                }
+                length => length as usize,
+            };
+            if !masked {
+                return Err(invalid_data("client frames must be masked"));
+            }
+            if self.message.len().saturating_add(length) > MAX_MESSAGE {
+                return Err(invalid_data("message too large"));
+            }
+
+            let mut mask = [0; 4];
+            self.reader.read_exact(&mut mask)?;
+            let mut payload = vec![0; length];
+            self.reader.read_exact(&mut payload)?;
+            for (index, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[index % 4];
+            }
+
+            // Control frames may arrive between the fragments of a message
+            match opcode {
+                0x8 => return Ok(Message::Close),
+                0x9 => return Ok(Message::Ping(payload)),
+                0xa => continue,
+                0x0 if self.opcode.is_none() => {
+                    return Err(invalid_data("continuation without a message"))
+                }
+                0x0 => (),
+                0x1 | 0x2 if self.opcode.is_none() => self.opcode = Some(opcode),
+                _ => return Err(invalid_data("unexpected opcode")),
+            }
+            self.message.extend_from_slice(&payload);
+            if finished {
+                break;
+            }
+        }
+
+        let message = mem::take(&mut self.message);
+        if self.opcode.take() == Some(0x1) {
+            String::from_utf8(message)
+                .map(Message::Text)
+                .map_err(|_| invalid_data("text is not UTF-8"))
+        } else {
+            Ok(Message::Binary(message))
+        }
+    }
+}
+
+pub fn write_text(writer: &mut impl Write, text: &str) -> io::Result<()> {
+    write_frame(writer, 0x1, text.as_bytes())
+}
+
+pub fn write_pong(writer: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    write_frame(writer, 0xa, data)
+}
+
+pub fn write_close(writer: &mut impl Write) -> io::Result<()> {
+    write_frame(writer, 0x8, &[])
+}
+
+/// Writes one unmasked, unfragmented frame, as servers send them.
+fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)?;
+
+    writer.flush()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// SHA-1, which the handshake needs even though it is no longer fit for
+/// anything that has to be secure.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut hash: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    // Pad to a whole number of 64 byte blocks, ending with the length in bits
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (index, word) in block.chunks(4).enumerate() {
+            words[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..80 {
+            words[index] =
+                (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16])
+                    .rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = hash;
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let next = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = next;
+        }
+        for (total, value) in hash.iter_mut().zip([a, b, c, d, e]) {
+            *total = total.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (index, value) in hash.iter().enumerate() {
+        digest[index * 4..index * 4 + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    digest
+}
+
+/// Standard base64 with padding.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    /// A masked client frame, as browsers send them.
+    fn client_frame(first: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![first, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+
+        frame
+    }
+
+    #[test]
+    fn accepts_the_rfc_sample_key() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn hashes_the_standard_test_vectors() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        // Long enough that the padding spills into a second block
+        assert_eq!(
+            hex(&sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn encodes_base64_with_padding() {
+        let encoded: Vec<String> = ["", "f", "fo", "foo", "foob", "fooba", "foobar"]
+            .iter()
+            .map(|text| base64(text.as_bytes()))
+            .collect();
+
+        assert_eq!(
+            encoded,
+            ["", "Zg==", "Zm8=", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "Zm9vYmFy"]
+        );
+    }
+
+    #[test]
+    fn joins_fragments_around_a_ping() {
+        let mut input = client_frame(0x01, b"hel");
+        input.extend(client_frame(0x89, b"are you there"));
+        input.extend(client_frame(0x80, b"lo"));
+        let mut reader = MessageReader::new(input.as_slice());
+
+        assert_eq!(
+            reader.read_message().unwrap(),
+            Message::Ping(b"are you there".to_vec())
+        );
+        assert_eq!(
+            reader.read_message().unwrap(),
+            Message::Text("hello".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_unmasked_frames() {
+        let mut frame = Vec::new();
+        write_text(&mut frame, "hi").unwrap();
+        assert_eq!(frame, [0x81, 2, b'h', b'i']);
+
+        let error = MessageReader::new(frame.as_slice())
+            .read_message()
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,135 @@
+/// The number of terminal columns text takes up. East Asian wide characters
+/// and emoji take two, while combining marks, variation selectors, skin tone
+/// modifiers and anything joined on with a zero width joiner take none. This
+/// covers the symbols players pick without a full Unicode width table.
+pub fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut joined = false;
+    for character in text.chars() {
+        if joined {
+            // The joined character is drawn as part of the one before
+            joined = false;
+            continue;
+        }
+        if character == '\u{200d}' {
+            joined = true;
+            continue;
+        }
+        width += char_width(character);
+    }
+
+    width
+}
+
+/// Pads text with spaces on the right to fill `width` columns.
+pub fn pad(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(display_width(text));
+
+    format!("{text}{}", " ".repeat(padding))
+}
+
+pub fn char_width(character: char) -> usize {
+    match character as u32 {
+        // Control characters, combining marks, zero width spaces and joiners,
+        // variation selectors and skin tone modifiers
+        0x00..=0x1f
+        | 0x7f..=0x9f
+        | 0x0300..=0x036f
+        | 0x200b..=0x200f
+        | 0x20d0..=0x20ff
+        | 0xfe00..=0xfe0f
+        | 0x1f3fb..=0x1f3ff
+        | 0xe0000..=0xe007f => 0,
+        // Hangul, CJK, fullwidth forms and the emoji blocks
+        0x1100..=0x115f
+        | 0x231a..=0x231b
+        | 0x2329..=0x232a
+        | 0x23e9..=0x23ec
+        | 0x23f0
+        | 0x23f3
+        | 0x25fd..=0x25fe
+        | 0x2614..=0x2615
+        | 0x2648..=0x2653
+        | 0x267f
+        | 0x2693
+        | 0x26a1
+        | 0x26aa..=0x26ab
+        | 0x26bd..=0x26be
+        | 0x26c4..=0x26c5
+        | 0x26ce
+        | 0x26d4
+        | 0x26ea
+        | 0x26f2..=0x26f3
+        | 0x26f5
+        | 0x26fa
+        | 0x26fd
+        | 0x2705
+        | 0x270a..=0x270b
+        | 0x2728
+        | 0x274c
+        | 0x274e
+        | 0x2753..=0x2755
+        | 0x2757
+        | 0x2795..=0x2797
+        | 0x27b0
+        | 0x27bf
+        | 0x2b1b..=0x2b1c
+        | 0x2b50
+        | 0x2b55
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f004
+        | 0x1f0cf
+        | 0x1f18e
+        | 0x1f191..=0x1f19a
+        | 0x1f200..=0x1f251
+        | 0x1f300..=0x1f64f
+        | 0x1f680..=0x1f6ff
+        | 0x1f7e0..=0x1f7eb
+        | 0x1f90c..=0x1f9ff
+        | 0x1fa70..=0x1faff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_narrow_and_wide_text() {
+        assert_eq!(display_width(""), 0);
+        assert_eq!(display_width("Alice"), 5);
+        assert_eq!(display_width("Zoë"), 3);
+        assert_eq!(display_width("李雷"), 4);
+        assert_eq!(display_width("ｘ"), 2);
+        assert_eq!(display_width("🦀 Ferris"), 9);
+    }
+
+    #[test]
+    fn modifiers_and_joined_characters_take_no_columns() {
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width("❤\u{fe0f}"), 1);
+        assert_eq!(display_width("👍🏽"), 2);
+        assert_eq!(display_width("👩\u{200d}💻"), 2);
+        assert_eq!(display_width("👨\u{200d}👩\u{200d}👧\u{200d}👦"), 2);
+        assert_eq!(display_width("🏳\u{fe0f}\u{200d}🌈"), 2);
+    }
+
+    #[test]
+    fn pads_to_the_display_width() {
+        assert_eq!(pad("A", 3), "A  ");
+        assert_eq!(pad("猫", 3), "猫 ");
+        assert_eq!(pad("👩\u{200d}💻", 4), "👩\u{200d}💻  ");
+        assert_eq!(pad("Alice", 3), "Alice");
+    }
+}
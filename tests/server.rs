@@ -0,0 +1,223 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use boxes::server::{self, Server};
+
+/// Starts a server on a free local port and returns its address.
+fn start_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = Server {
+        port: 0,
+        forfeit_after: Duration::from_secs(60),
+        archive_dir: None,
+        config: None,
+    };
+    thread::spawn(move || server::serve(listener, server));
+
+    address
+}
+
+/// A connection to the server, read a line at a time.
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn open(address: &str) -> Connection {
+        let stream = TcpStream::connect(address).unwrap();
+        // Fail the test rather than hang when an expected line never comes
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+
+        Connection {
+            writer: stream.try_clone().unwrap(),
+            reader: BufReader::new(stream),
+        }
+    }
+
+    fn send(&mut self, line: &str) {
+        writeln!(self.writer, "{line}").unwrap();
+    }
+
+    fn line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        assert!(!line.is_empty(), "the server closed the connection");
+
+        line.trim_end().to_string()
+    }
+
+    /// Reads past boards and messages to the next line, which must be of
+    /// the given kind, and returns what follows the kind.
+    fn expect(&mut self, kind: &str) -> String {
+        loop {
+            let line = self.line();
+            let (found, text) = line.split_once(' ').unwrap_or((&line, ""));
+            if found == "board" || found == "message" {
+                continue;
+            }
+            assert_eq!(found, kind, "expected {kind}, got {line:?}");
+            return text.to_string();
+        }
+    }
+
+    /// Takes a seat and returns its player number and rejoin token.
+    fn join(&mut self, command: &str) -> (String, String) {
+        self.send(command);
+        let seat = self.expect("seat");
+        let fields: Vec<&str> = seat.split(' ').collect();
+
+        (fields[1].to_string(), fields[3].to_string())
+    }
+}
+
+/// Has `players[mover]` play a move and checks that every player hears
+/// about it, then reads the scores and returns them. `played` is the
+/// mover's symbol and name around the move.
+fn play(players: &mut [&mut Connection], mover: usize, played: (&str, &str), edge: &str) -> String {
+    players[mover].send(&format!("move {edge}"));
+    let (symbol, name) = played;
+    let mut scores = Vec::new();
+    for player in players.iter_mut() {
+        assert_eq!(player.expect("played"), format!("{symbol} {edge} {name}"));
+        scores.push(player.expect("scores"));
+    }
+    assert!(scores.iter().all(|other| *other == scores[0]));
+
+    scores.remove(0)
+}
+
+#[test]
+fn two_clients_play_a_game() {
+    let address = start_server();
+    let mut alice = Connection::open(&address);
+    let mut bob = Connection::open(&address);
+
+    alice.send("create --name A=Alice --name B=Bob 2 2 2");
+    assert_eq!(alice.expect("created"), "1");
+    alice.expect("ok");
+    assert_eq!(alice.join("join 1").0, "1");
+    assert_eq!(bob.join("join").0, "2");
+
+    let mut players = [&mut alice, &mut bob];
+    for player in players.iter_mut() {
+        assert_eq!(player.expect("scores"), "Scores: Alice (A) 0, Bob (B) 0");
+        assert_eq!(player.expect("turn"), "1 A Alice");
+    }
+
+    players[1].send("move 0 0 h");
+    assert_eq!(players[1].expect("error"), "it is not your turn");
+
+    let (alice_plays, bob_plays) = (("A", "Alice"), ("B", "Bob"));
+    let scores = play(&mut players, 0, alice_plays, "0 0 h");
+    assert_eq!(scores, "Scores: Alice (A) 0, Bob (B) 0");
+    for player in players.iter_mut() {
+        assert_eq!(player.expect("turn"), "2 B Bob");
+    }
+    play(&mut players, 1, bob_plays, "1 0 h");
+    for player in players.iter_mut() {
+        assert_eq!(player.expect("turn"), "1 A Alice");
+    }
+    play(&mut players, 0, alice_plays, "0 0 v");
+    for player in players.iter_mut() {
+        assert_eq!(player.expect("turn"), "2 B Bob");
+    }
+
+    // The last edge completes the only box
+    let scores = play(&mut players, 1, bob_plays, "0 1 v");
+    assert_eq!(scores, "Scores: Alice (A) 0, Bob (B) 1");
+    for player in players.iter_mut() {
+        let mut results = Vec::new();
+        loop {
+            match player.line().split_once(' ') {
+                Some(("result", line)) => results.push(line.to_string()),
+                None => break,
+                Some(_) => (),
+            }
+        }
+        assert!(results.iter().any(|line| line == "Winner(s): Bob"));
+    }
+}
+
+#[test]
+fn a_seat_is_only_given_back_for_its_token() {
+    let address = start_server();
+    let mut alice = Connection::open(&address);
+    let mut bob = Connection::open(&address);
+
+    alice.send("create 2 2 2");
+    alice.expect("created");
+    alice.expect("ok");
+    let (_, token) = alice.join("join 1");
+    bob.join("join 1");
+    bob.expect("scores");
+    bob.expect("turn");
+    drop(alice);
+    while !bob.line().starts_with("message A disconnected") {}
+
+    let mut mallory = Connection::open(&address);
+    mallory.send("join 1");
+    assert_eq!(mallory.expect("error"), "the game is full");
+    mallory.send("join 1 0123456789abcdef0123456789abcdef");
+    assert_eq!(mallory.expect("error"), "no seat is waiting for that token");
+
+    let mut alice = Connection::open(&address);
+    let (player, rejoined) = alice.join(&format!("join 1 {token}"));
+    assert_eq!((player, rejoined), ("1".to_string(), token));
+    assert_eq!(alice.expect("scores"), "Scores: A 0, B 0");
+    assert_eq!(alice.expect("turn"), "1 A A");
+}
+
+#[test]
+fn bots_move_without_holding_up_the_lobby() {
+    let address = start_server();
+    let mut watcher = Connection::open(&address);
+    watcher.send("create --bot A=mcts:2s --bot B=mcts:2s 8 8 2");
+    watcher.expect("created");
+    watcher.expect("ok");
+
+    let mut player = Connection::open(&address);
+    player.send("create --bot B=greedy 2 2 2");
+    assert_eq!(player.expect("created"), "2");
+    player.expect("ok");
+    player.join("join 2");
+    player.expect("scores");
+    player.expect("turn");
+    player.send("move 0 0 h");
+    assert_eq!(player.expect("played"), "A 0 0 h A");
+    player.expect("scores");
+
+    // The greedy bot replies while the MCTS bots are still thinking
+    let reply = player.expect("played");
+    assert!(reply.starts_with("B "), "{reply}");
+}
+
+#[test]
+fn create_is_refused_once_too_many_games_are_open() {
+    let address = start_server();
+    let mut connections: Vec<Connection> = (0..17).map(|_| Connection::open(&address)).collect();
+
+    // Each connection may have four games open, and the server sixty-four
+    for connection in connections.iter_mut().take(16) {
+        for _ in 0..4 {
+            connection.send("create 2 2 2");
+            connection.expect("created");
+            connection.expect("ok");
+        }
+    }
+    connections[0].send("create 2 2 2");
+    assert_eq!(
+        connections[0].expect("error"),
+        "you already have 4 open games"
+    );
+    connections[16].send("create 2 2 2");
+    assert_eq!(
+        connections[16].expect("error"),
+        "the server already has 64 open games"
+    );
+}